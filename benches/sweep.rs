@@ -0,0 +1,65 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use normalisation::apply_normalisation::{NormalizationConfig, Normalise};
+use normalisation::stage2::{Axis, SortedAxis, Translation};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// A beadset of one sample: homozygote A and B arms along the axes and the heterozygotes between them
+fn beadset(points: usize) -> Vec<(f64, f64)> {
+    let mut rng = StdRng::seed_from_u64(7);
+    (0..points)
+        .map(|_| {
+            let intensity = rng.gen_range(2000.0..12000.0);
+            let (x, y) = match rng.gen_range(0..3) {
+                0 => (intensity, intensity * 0.08),
+                1 => (intensity * 0.08, intensity),
+                _ => (intensity * 0.5, intensity * 0.45),
+            };
+            (x + rng.gen_range(-150.0..150.0), y + rng.gen_range(-150.0..150.0))
+        })
+        .collect()
+}
+
+fn sweep_positions(data: &[(f64, f64)], sweep_points: usize) -> Vec<f64> {
+    let x_min = data.iter().map(|&(x, _)| x).fold(f64::INFINITY, f64::min);
+    let x_max = data.iter().map(|&(x, _)| x).fold(f64::NEG_INFINITY, f64::max);
+    let step = (x_max - x_min) / (sweep_points - 1) as f64;
+    (0..sweep_points).map(|i| x_min + i as f64 * step).collect()
+}
+
+// One sweep of the x axis, scanning the whole beadset per grid point against binary search on the sorted points
+fn sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("x sweep of 400 points");
+    for points in [1_000, 10_000, 50_000] {
+        let data = beadset(points);
+        let positions = sweep_positions(&data, 400);
+
+        group.bench_with_input(BenchmarkId::new("linear scan", points), &data, |b, data| {
+            b.iter(|| positions.iter().map(|&x| Translation::find_closest(x, 'x', data)).collect::<Vec<_>>())
+        });
+        group.bench_with_input(BenchmarkId::new("sorted axis", points), &data, |b, data| {
+            b.iter(|| {
+                let by_x = SortedAxis::new(data, Axis::X);
+                positions.iter().map(|&x| by_x.nearest(x)).collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+}
+
+// Every stage of the within-BeadSetID normalisation of one beadset
+fn fit(c: &mut Criterion) {
+    let config = NormalizationConfig::default();
+    let mut group = c.benchmark_group("fit transform");
+    group.sample_size(20);
+    for points in [1_000, 10_000, 50_000] {
+        let data = beadset(points);
+        group.bench_with_input(BenchmarkId::from_parameter(points), &data, |b, data| {
+            b.iter(|| Normalise::fit_transform(black_box(data), &config).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sweep, fit);
+criterion_main!(benches);
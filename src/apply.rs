@@ -0,0 +1,34 @@
+use crate::config::RunConfig;
+use crate::idat_processing;
+use normalisation::apply_normalisation::TransformTable;
+use normalisation::manifest::hash_file;
+use normalisation::{Error, Result};
+
+// Normalises every selected sample within its BeadSetIDs with the transforms of an earlier run, nothing is refitted
+fn apply_transforms(config: &RunConfig, transforms: &str) -> Result<()> {
+    // Unlike normalise, apply has nothing to show for itself without an output directory
+    config.output_directory()?;
+    let table = TransformTable::read(transforms)?;
+
+    // The transforms are fitted to the BeadSetID groups of one manifest, another manifest may group the SNPs differently
+    let manifest = config.manifest()?;
+    if table.manifest_hash != hash_file(manifest)? {
+        return Err(Error::InvalidInput(format!("{} was not fitted with the manifest {}, normalise with it again", transforms, manifest)));
+    }
+
+    let stored = table.into_samples();
+    println!("Read the transforms of {} samples from {}", stored.len(), transforms);
+
+    let mut number_of_individuals: i32 = 0;
+    let normalised = idat_processing::main_processing(0, 1, &mut number_of_individuals, config, Some(&stored))?;
+
+    idat_processing::write_individuals(&normalised.samples, &normalised.individuals, &normalised.layout, config)?;
+    println!("Normalised {} of {} individuals with the stored transforms", normalised.individuals.len(), number_of_individuals);
+    Ok(())
+}
+
+// Entry point of the apply subcommand, returns the process exit code
+// Each sample is written to <output directory>/<sample>.tsv
+pub fn run(config: &RunConfig, transforms: &str) -> i32 {
+    crate::exit_code(apply_transforms(config, transforms))
+}
@@ -0,0 +1,53 @@
+use crate::error::{invalid_input, Result};
+use crate::stage1::MIN_POINTS;
+use serde::{Deserialize, Serialize};
+
+// The settings of the stages, the defaults are those of the GenomeStudio normalisation
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NormalizationConfig {
+    pub sweep_points: usize,   // Points of the grid each stage sweeps across the range of an axis
+    pub outlier_rank: usize,   // Points up to the rank-th smallest or from the rank-th largest value are outliers ...
+    pub lower_percentile: f64, // ... or up to this percentile and from the upper one, as fractions between 0 and 1,
+    pub upper_percentile: f64, // whichever leaves out fewer
+    pub fit_tolerance: f64,    // Singular values below this are treated as zero when fitting lines
+}
+
+impl Default for NormalizationConfig {
+    fn default() -> NormalizationConfig {
+        NormalizationConfig {
+            sweep_points: 400,
+            outlier_rank: 5,
+            lower_percentile: 0.01,
+            upper_percentile: 0.99,
+            fit_tolerance: 1.0e-10,
+        }
+    }
+}
+
+impl NormalizationConfig {
+
+    pub fn validate(&self) -> Result<()> {
+        if self.sweep_points < 2 {
+            return Err(invalid_input(format!("sweep_points must be at least 2, found {}", self.sweep_points)));
+        }
+        if self.outlier_rank < 1 {
+            return Err(invalid_input("outlier_rank must be at least 1".to_string()));
+        }
+        if !(0.0 <= self.lower_percentile && self.lower_percentile < self.upper_percentile && self.upper_percentile <= 1.0) {
+            return Err(invalid_input(format!(
+                "The outlier percentiles must satisfy 0 <= lower_percentile < upper_percentile <= 1, found {} and {}",
+                self.lower_percentile, self.upper_percentile
+            )));
+        }
+        if self.fit_tolerance.is_nan() || self.fit_tolerance < 0.0 {
+            return Err(invalid_input(format!("fit_tolerance must not be negative, found {}", self.fit_tolerance)));
+        }
+        Ok(())
+    }
+
+    // The fewest points a group needs, for the outlier thresholds and for the lines fitted through what is left
+    pub fn min_points(&self) -> usize {
+        self.outlier_rank.max(MIN_POINTS)
+    }
+}
@@ -1,76 +1,139 @@
+use crate::error::{Error, Result};
 use crate::stage1::Outliers;
 use crate::stage2::Translation;
 use crate::stage3::Rotation;
 use crate::stage4::Shear;
 use crate::stage5::Scale;
-use std::error::Error;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 
+mod config;
+mod table;
+mod transform;
+
+pub use config::NormalizationConfig;
+pub use table::{TransformRecord, TransformTable, TRANSFORM_TSV_HEADER};
+pub use transform::NormalizationTransform;
+
 pub struct Normalise;
 
 impl Normalise {
 
-    pub fn within_beadset_normalisation(data: &mut HashMap<i32, Vec<(f64,f64)>>, vector_names: &Arc<Mutex<Vec<i32>>>) -> Result<(), Box<dyn Error>> {
+    // Fits the transform of a group of points with the stages and returns it with the number of outliers left out of the fit
+    // The points are left as they were, callers normalise them with the transform
+    pub fn fit_transform(data: &[(f64, f64)], config: &NormalizationConfig) -> Result<(NormalizationTransform, usize)> {
+        let mut inliers = data.to_vec();
+
+        // Stage 1 - remove outliers
+        let outliers = Outliers::remove_outliers_parallelised(&mut inliers, config)?.len();
+
+        // Stage 2 - Translation
+        let (offset_x, offset_y) = Translation::transform_p(&mut inliers, config);
+
+        // Stage 3 - Rotation
+        let theta: f64 = Rotation::rotate_p(&mut inliers, offset_x, offset_y, config);
+
+        // Stage 4
+        let shear = Shear::shear_p(&mut inliers, theta, config);
+
+        // Stage 5
+        let (scale_x, scale_y) = Scale::scale_p(&mut inliers, shear, config);
+
+        Ok((NormalizationTransform { offset_x, offset_y, theta, shear, scale_x, scale_y }, outliers))
+    }
+
+    // Normalises every BeadSetID group of a sample and returns their transforms, in the order of vector_names
+    // Empty groups are skipped. Groups with too few points to normalise are left as they are, with the identity
+    // as their transform, and returned as DegenerateBeadset errors for the caller to report
+    pub fn within_beadset_normalisation(
+        sample: &str,
+        data: &mut HashMap<i32, Vec<(f64,f64)>>,
+        vector_names: &Arc<Mutex<Vec<i32>>>,
+        config: &NormalizationConfig,
+    ) -> Result<(Vec<TransformRecord>, Vec<Error>)> {
 
-        //let mut data = beadset_id_vector.lock().unwrap();
         let vector_names = vector_names.lock().unwrap();
+        let mut transforms = Vec::with_capacity(vector_names.len());
+        let mut degenerate = Vec::new();
 
         for &name in &*vector_names {
-            if let Some(data_vector) = data.get_mut(&name) {
-                // Stage 1 - remove outliers
-                if let Ok(removed_outliers) = Outliers::remove_outliers_parallelised(data_vector) {
-                        // // Stage 2 - Translation
-                        let (offset_x, offset_y) = Translation::transform_p(data_vector);
-
-                        // // Stage 3 - Rotation
-                        let theta:f64 = Rotation::rotate_p(data_vector,offset_x,offset_y);
-
-                        // // Stage 4
-                        let shear = Shear::shear_p(data_vector,theta);
-
-                        // // Stage 5
-                        Scale::scale_p(data_vector,shear);
-
-                        for (index, outlier) in removed_outliers {
-                            data_vector.insert(index, outlier);
-                        }
-                } else {
-                    println!("Outliers function returned an error, skipping transform_p and other functions.");
+            let Some(data_vector) = data.get_mut(&name).filter(|data_vector| !data_vector.is_empty()) else {
+                continue;
+            };
+
+            let (transform, outliers) = match Self::fit_transform(data_vector, config) {
+                Ok(fitted) => fitted,
+                Err(Error::TooFewPoints { points, .. }) => {
+                    println!("Sample {}: BeadSetID {} has too few points to normalise and is left as it is", sample, name);
+                    degenerate.push(Error::DegenerateBeadset { bead_set_id: name, points });
+                    (NormalizationTransform::IDENTITY, 0)
                 }
-            }
+                Err(err) => return Err(err),
+            };
+
+            // The outliers are normalised with the rest of the group
+            transform.apply_all(data_vector);
+            transforms.push(TransformRecord {
+                sample: sample.to_string(),
+                bead_set_id: name,
+                points: data_vector.len(),
+                outliers,
+                transform,
+            });
         }
 
-        Ok(())
+        Ok((transforms, degenerate))
     }
 
 
-    pub fn within_snp_normalisation(beadset_id_vector: &Arc<Mutex<Vec<(f64,f64)>>>) -> Result<(), Box<dyn Error>> {
-        let mut data = beadset_id_vector.lock().unwrap();
+    // Normalises every BeadSetID group of a sample with the transforms of an earlier run instead of fitting new ones
+    // Gives the same intensities, bit for bit, as the run the transforms were fitted in
+    pub fn apply_beadset_transforms(
+        sample: &str,
+        data: &mut HashMap<i32, Vec<(f64,f64)>>,
+        vector_names: &Arc<Mutex<Vec<i32>>>,
+        transforms: &[TransformRecord],
+    ) -> Result<()> {
 
-        // Stage 1 - remove outliers
-        if let Ok(removed_outliers) = Outliers::remove_outliers_parallelised(&mut data) {
+        let vector_names = vector_names.lock().unwrap();
 
-            // Stage 2 - Translation
-            let (offset_x, offset_y) = Translation::transform_p(&mut data);
+        // Empty groups have no transform, as in within_beadset_normalisation
+        for &name in &*vector_names {
+            if let Some(data_vector) = data.get_mut(&name).filter(|data_vector| !data_vector.is_empty()) {
+                let record = transforms
+                    .iter()
+                    .find(|record| record.bead_set_id == name)
+                    .ok_or_else(|| Error::MissingTransform { sample: sample.to_string(), bead_set_id: name })?;
+
+                // A transform fitted to another set of SNPs would silently normalise the wrong points
+                if record.points != data_vector.len() {
+                    return Err(Error::InvalidData {
+                        path: None,
+                        message: format!(
+                            "The stored transform of BeadSetID {} was fitted to {} points, the sample has {}",
+                            name, record.points, data_vector.len()
+                        ),
+                    });
+                }
 
-            // Stage 3 - Rotation
-            let theta:f64 = Rotation::rotate_p(&mut data,offset_x,offset_y);
+                record.transform.apply_all(data_vector);
+            }
+        }
 
-            // Stage 4
-            let shear = Shear::shear_p(&mut data,theta);
+        Ok(())
+    }
 
-            // Stage 5
-            Scale::scale_p(&mut data,shear);
 
-            for (index, outlier) in removed_outliers {
-                data.insert(index, outlier);
-            }
+    // Fails, leaving the SNP unchanged, when it has too few points to normalise
+    pub fn within_snp_normalisation(
+        beadset_id_vector: &Arc<Mutex<Vec<(f64,f64)>>>,
+        config: &NormalizationConfig,
+    ) -> Result<NormalizationTransform> {
+        let mut data = beadset_id_vector.lock().unwrap();
 
-        } else {
-            println!("Outliers function returned an error, skipping transform_p and other_function.");
-        }
+        let (transform, _) = Self::fit_transform(&data, config)?;
+        transform.apply_all(&mut data);
 
-        Ok(())
+        Ok(transform)
     }
 }
@@ -0,0 +1,154 @@
+use super::NormalizationTransform;
+use crate::error::{invalid_data, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+pub const TRANSFORM_TSV_HEADER: &str = "sample\tbead_set_id\tpoints\toutliers\toffset_x\toffset_y\ttheta\tshear\tscale_x\tscale_y";
+
+// First line of the TSV, followed by the hash of the manifest the transforms were fitted with
+const MANIFEST_HASH_LINE: &str = "# manifest_hash\t";
+
+// The transform fitted to one BeadSetID of one sample and the points it was fitted to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransformRecord {
+    pub sample: String,
+    pub bead_set_id: i32,
+    pub points: usize,   // Points of the group, outliers included
+    pub outliers: usize, // Points left out of the fit
+    #[serde(flatten)]
+    pub transform: NormalizationTransform,
+}
+
+// The transforms of every sample and BeadSetID of a run, written next to its results
+// Numbers are written in their shortest exact form, so reading a file back gives the same transforms bit for bit
+// Only the TSV can hold the NaN and infinite parameters of a degenerate fit, JSON writes them as null
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformTable {
+    pub manifest_hash: u64, // hash_file of the manifest, the transforms only apply to the SNPs of that manifest
+    pub records: Vec<TransformRecord>,
+}
+
+impl TransformTable {
+
+    // Keeps the records in sample then BeadSetID order, whichever order the samples were normalised in
+    pub fn sort(&mut self) {
+        self.records.sort_by(|a, b| (&a.sample, a.bead_set_id).cmp(&(&b.sample, b.bead_set_id)));
+    }
+
+    pub fn find(&self, sample: &str, bead_set_id: i32) -> Option<&TransformRecord> {
+        self.records.iter().find(|record| record.sample == sample && record.bead_set_id == bead_set_id)
+    }
+
+    // Splits the table into the records of each sample, so that large cohorts are not searched once per sample
+    pub fn into_samples(self) -> HashMap<String, Vec<TransformRecord>> {
+        let mut samples: HashMap<String, Vec<TransformRecord>> = HashMap::new();
+        for record in self.records {
+            samples.entry(record.sample.clone()).or_default().push(record);
+        }
+        samples
+    }
+
+    // Reads a table written by write_json or write_tsv, told apart by the extension
+    pub fn read(path: &str) -> Result<TransformTable> {
+        if path.ends_with(".json") {
+            Self::read_json(path)
+        } else {
+            Self::read_tsv(path)
+        }
+    }
+
+    pub fn write_json(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(|err| invalid_data(err.to_string()).in_file(path))?;
+        fs::write(path, json).map_err(|err| Error::from(err).in_file(path))
+    }
+
+    pub fn write_tsv(&self, path: &str) -> Result<()> {
+        self.write_tsv_to(File::create(path).map_err(|err| Error::from(err).in_file(path))?)
+            .map_err(|err| Error::from(err).in_file(path))
+    }
+
+    pub fn write_tsv_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = BufWriter::new(writer);
+        writeln!(writer, "{}{}", MANIFEST_HASH_LINE, self.manifest_hash)?;
+        writeln!(writer, "{}", TRANSFORM_TSV_HEADER)?;
+        for record in &self.records {
+            let transform = &record.transform;
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                record.sample,
+                record.bead_set_id,
+                record.points,
+                record.outliers,
+                transform.offset_x,
+                transform.offset_y,
+                transform.theta,
+                transform.shear,
+                transform.scale_x,
+                transform.scale_y
+            )?;
+        }
+        writer.flush()
+    }
+
+    pub fn read_json(path: &str) -> Result<TransformTable> {
+        let json = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+        serde_json::from_str(&json).map_err(|err| invalid_data(err.to_string()).in_file(path))
+    }
+
+    pub fn read_tsv(path: &str) -> Result<TransformTable> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_tsv(&contents).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_tsv(contents: &str) -> Result<TransformTable> {
+        let mut lines = contents.lines();
+        let manifest_hash = lines
+            .next()
+            .and_then(|line| line.trim_end().strip_prefix(MANIFEST_HASH_LINE))
+            .and_then(|hash| hash.parse().ok())
+            .ok_or_else(|| invalid_data("Transform table does not start with the hash of the manifest it was fitted with".to_string()))?;
+        if lines.next().map(str::trim_end) != Some(TRANSFORM_TSV_HEADER) {
+            return Err(invalid_data(format!("Transform table does not start with the header \"{}\"", TRANSFORM_TSV_HEADER)));
+        }
+
+        let mut records = Vec::new();
+        for (line_number, line) in lines.enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.trim_end().split('\t').collect();
+            if fields.len() != 10 {
+                return Err(invalid_data(format!("Line {} of the transform table has {} fields, expected 10", line_number + 3, fields.len())));
+            }
+
+            let number = |index: usize| {
+                fields[index].parse::<f64>().map_err(|_| {
+                    invalid_data(format!("Line {} of the transform table has an invalid number: {}", line_number + 3, fields[index]))
+                })
+            };
+            let count = |index: usize| {
+                fields[index].parse::<usize>().map_err(|_| {
+                    invalid_data(format!("Line {} of the transform table has an invalid count: {}", line_number + 3, fields[index]))
+                })
+            };
+            records.push(TransformRecord {
+                sample: fields[0].to_string(),
+                bead_set_id: fields[1].parse().map_err(|_| {
+                    invalid_data(format!("Line {} of the transform table has an invalid BeadSetID: {}", line_number + 3, fields[1]))
+                })?,
+                points: count(2)?,
+                outliers: count(3)?,
+                transform: NormalizationTransform {
+                    offset_x: number(4)?,
+                    offset_y: number(5)?,
+                    theta: number(6)?,
+                    shear: number(7)?,
+                    scale_x: number(8)?,
+                    scale_y: number(9)?,
+                },
+            });
+        }
+
+        Ok(TransformTable { manifest_hash, records })
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+// The affine correction fitted to one BeadSetID of one sample, or to one SNP across the individuals
+// Applied in the order of the stages: translation, rotation by theta, shear along x, then scaling of each axis
+// theta and shear are angles in radians, as returned by the rotation and shear stages
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NormalizationTransform {
+    pub offset_x: f64,
+    pub offset_y: f64,
+    pub theta: f64,
+    pub shear: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+}
+
+impl Default for NormalizationTransform {
+    fn default() -> NormalizationTransform {
+        NormalizationTransform::IDENTITY
+    }
+}
+
+impl NormalizationTransform {
+
+    pub const IDENTITY: NormalizationTransform = NormalizationTransform {
+        offset_x: 0.0,
+        offset_y: 0.0,
+        theta: 0.0,
+        shear: 0.0,
+        scale_x: 1.0,
+        scale_y: 1.0,
+    };
+
+    // Moves the point of intersection of the homozygote lines to the origin
+    pub fn translate(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (x - self.offset_x, y - self.offset_y)
+    }
+
+    // Turns the homozygote A line onto the x axis
+    pub fn rotate(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (sin_theta, cos_theta) = self.theta.sin_cos();
+        (x * cos_theta + y * sin_theta, -x * sin_theta + y * cos_theta)
+    }
+
+    // Turns the homozygote B line onto the y axis
+    pub fn unshear(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (x - self.shear.tan() * y, y)
+    }
+
+    pub fn scale(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (x / self.scale_x, y / self.scale_y)
+    }
+
+    // Normalises a raw (x, y) intensity
+    pub fn apply(&self, point: (f64, f64)) -> (f64, f64) {
+        self.scale(self.unshear(self.rotate(self.translate(point))))
+    }
+
+    // Recovers the raw intensity of a normalised one, up to rounding
+    pub fn invert(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (x, y) = (x * self.scale_x, y * self.scale_y);
+        let (x, y) = (x + self.shear.tan() * y, y);
+        let (sin_theta, cos_theta) = self.theta.sin_cos();
+        let (x, y) = (x * cos_theta - y * sin_theta, x * sin_theta + y * cos_theta);
+        (x + self.offset_x, y + self.offset_y)
+    }
+
+    pub fn apply_all(&self, data: &mut [(f64, f64)]) {
+        for point in data.iter_mut() {
+            *point = self.apply(*point);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        // Every field is a number, so this cannot fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<NormalizationTransform> {
+        serde_json::from_str(json)
+    }
+}
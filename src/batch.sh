@@ -7,7 +7,7 @@
 #SBATCH --error=error.txt  # Error file
 
 # Load any necessary modules or set environment variables here
-cargo build --release
+cargo build --release --features mpi
 
 # Run your Rust MPI program with mpirun
-mpirun -n 4 ../target/release/final_code  /dataE/AWIGenGWAS/aux/sample_sheet.csv /dataE/AWIGenGWAS/idats /dataE/AWIGenGWAS/aux/H3Africa_2017_20021485_A3.csv
+mpirun -n 4 ../target/release/final_code normalise --sample-sheet /dataE/AWIGenGWAS/aux/sample_sheet.csv --idat-dir /dataE/AWIGenGWAS/idats --manifest /dataE/AWIGenGWAS/aux/H3Africa_2017_20021485_A3.csv
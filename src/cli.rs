@@ -0,0 +1,138 @@
+use crate::config::RunConfig;
+use clap::{Args, Parser, Subcommand};
+use normalisation::Result;
+
+#[derive(Debug, Parser)]
+#[command(name = "final_code", version, about = "Normalises the intensities of Illumina genotyping arrays")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Normalise the intensities of every selected sample (run under mpirun)")]
+    Normalise(RunArgs),
+    #[command(about = "Print the header, field table and statistics of IDAT files")]
+    Inspect(InspectArgs),
+    #[command(about = "Check every selected sample against the manifest without normalising")]
+    Qc(RunArgs),
+    #[command(about = "Write the per-SNP X/Y intensities of every selected sample as TSV files")]
+    Export(ExportArgs),
+    #[command(about = "Normalise every selected sample within its BeadSetIDs with the transforms of an earlier run")]
+    Apply(ApplyArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    #[arg(long, help = "Print the summaries as JSON")]
+    pub json: bool,
+    #[arg(required = true, value_name = "IDAT", help = "IDAT files, plain or gzip-compressed")]
+    pub idats: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    #[arg(long, help = "Write the intensities normalised within BeadSetIDs and within SNPs instead of the raw ones")]
+    pub normalised: bool,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    #[arg(long, value_name = "FILE", help = "transforms.tsv or transforms.json written by an earlier normalise run")]
+    pub transforms: String,
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+// The options of the subcommands that run the pipeline; each overrides the matching setting of the config file
+#[derive(Debug, Clone, Default, Args)]
+pub struct RunArgs {
+    #[arg(long, value_name = "FILE", help = "TOML config file with the settings below")]
+    pub config: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Manifest, binary (.bpm) or CSV")]
+    pub manifest: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Sample sheet; without one the samples are discovered under the IDAT directory")]
+    pub sample_sheet: Option<String>,
+    #[arg(long = "idat-dir", value_name = "DIR", help = "Directory the IDAT path template is relative to")]
+    pub idat_directory: Option<String>,
+    #[arg(long, value_name = "TEMPLATE", help = "IDAT path, e.g. \"{barcode}/{barcode}_{position}_{channel}.idat\"")]
+    pub idat_template: Option<String>,
+    #[arg(long = "output-dir", value_name = "DIR", help = "Directory the results and the resolved config are written to")]
+    pub output_directory: Option<String>,
+    #[arg(long, value_name = "N", help = "Worker threads per process")]
+    pub threads: Option<usize>,
+
+    #[arg(long, value_name = "ID,...", value_delimiter = ',', help = "Only process these sample IDs")]
+    pub samples: Vec<String>,
+    #[arg(long, value_name = "FILE", help = "Only process the sample IDs listed in this file, one per line")]
+    pub include: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Leave out the sample IDs listed in this file")]
+    pub exclude: Option<String>,
+    #[arg(long = "batch", value_name = "COMMENT,...", value_delimiter = ',', help = "Only process these batches")]
+    pub batches: Vec<String>,
+    #[arg(long, value_name = "FIRST-LAST", help = "Only process these samples of the sample sheet, counted from 1")]
+    pub rows: Option<String>,
+
+    #[arg(long, value_name = "COLUMN", help = "Sample sheet column with the sample ID")]
+    pub sample_column: Option<String>,
+    #[arg(long, value_name = "COLUMN", help = "Sample sheet column with the Sentrix barcode")]
+    pub barcode_column: Option<String>,
+    #[arg(long, value_name = "COLUMN", help = "Sample sheet column with the Sentrix position")]
+    pub position_column: Option<String>,
+    #[arg(long, value_name = "COLUMN", help = "Sample sheet column with the batch comment")]
+    pub batch_column: Option<String>,
+
+    #[arg(long, value_name = "PERCENT", help = "Minimum share of the manifest addresses a sample must have")]
+    pub min_match: Option<f64>,
+    #[arg(long, help = "Skip samples below the minimum instead of refusing to normalise")]
+    pub quarantine: bool,
+}
+
+impl RunArgs {
+
+    // Reads the config file, when one is given, and applies the command-line options on top of it
+    pub fn resolve(&self) -> Result<RunConfig> {
+        let mut config = match &self.config {
+            Some(path) => RunConfig::read(path)?,
+            None => RunConfig::default(),
+        };
+
+        let set = |setting: &mut Option<String>, value: &Option<String>| {
+            if value.is_some() {
+                setting.clone_from(value);
+            }
+        };
+        set(&mut config.manifest, &self.manifest);
+        set(&mut config.sample_sheet, &self.sample_sheet);
+        set(&mut config.idat_directory, &self.idat_directory);
+        set(&mut config.idat_template, &self.idat_template);
+        set(&mut config.output_directory, &self.output_directory);
+        set(&mut config.selection.include_file, &self.include);
+        set(&mut config.selection.exclude_file, &self.exclude);
+        set(&mut config.selection.rows, &self.rows);
+        set(&mut config.columns.sample_id, &self.sample_column);
+        set(&mut config.columns.sentrix_barcode, &self.barcode_column);
+        set(&mut config.columns.sentrix_position, &self.position_column);
+        set(&mut config.columns.batch, &self.batch_column);
+
+        if self.threads.is_some() {
+            config.threads = self.threads;
+        }
+        if !self.samples.is_empty() {
+            config.selection.samples.clone_from(&self.samples);
+        }
+        if !self.batches.is_empty() {
+            config.selection.batches.clone_from(&self.batches);
+        }
+        if let Some(min_match) = self.min_match {
+            config.normalisation.min_match_percentage = min_match;
+        }
+        config.normalisation.quarantine |= self.quarantine;
+
+        config.validate()?;
+        Ok(config)
+    }
+}
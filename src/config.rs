@@ -0,0 +1,154 @@
+use normalisation::apply_normalisation::NormalizationConfig;
+use normalisation::manifest::DEFAULT_MIN_MATCH_PERCENTAGE;
+use normalisation::sample_sheet::{ColumnMapping, SampleSelection};
+use normalisation::error::invalid_input;
+use normalisation::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// Which samples of the sample sheet to process, all of them when empty
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectionConfig {
+    pub samples: Vec<String>,
+    pub include_file: Option<String>, // One sample ID per line
+    pub exclude_file: Option<String>,
+    pub batches: Vec<String>,
+    pub rows: Option<String>, // e.g. "1-20", counted from 1
+}
+
+// Sample sheet column names, detected from the sample sheet when none are given
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColumnsConfig {
+    pub sample_id: Option<String>,
+    pub sentrix_barcode: Option<String>,
+    pub sentrix_position: Option<String>,
+    pub batch: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NormalisationConfig {
+    pub min_match_percentage: f64, // Samples covering less of the manifest are refused or quarantined
+    pub quarantine: bool,          // Skip such samples and carry on, rather than refusing to normalise the cohort
+    pub fit: NormalizationConfig,  // [normalisation.fit], the sweeps, outlier thresholds and line fits of the stages
+}
+
+impl Default for NormalisationConfig {
+    fn default() -> NormalisationConfig {
+        NormalisationConfig {
+            min_match_percentage: DEFAULT_MIN_MATCH_PERCENTAGE,
+            quarantine: false,
+            fit: NormalizationConfig::default(),
+        }
+    }
+}
+
+// Everything a run needs, read from the TOML config file and overridden by the command-line options
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    pub manifest: Option<String>,
+    pub sample_sheet: Option<String>, // Without one the samples are discovered under the IDAT directory
+    pub idat_directory: Option<String>,
+    pub idat_template: Option<String>,
+    pub output_directory: Option<String>,
+    pub threads: Option<usize>, // Worker threads per process, all cores when not set
+    pub selection: SelectionConfig,
+    pub columns: ColumnsConfig,
+    pub normalisation: NormalisationConfig,
+}
+
+impl RunConfig {
+
+    pub fn read(path: &str) -> Result<RunConfig> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+        toml::from_str(&contents).map_err(|err| invalid_input(format!("Config file {} is not valid: {}", path, err)))
+    }
+
+    pub fn to_toml(&self) -> String {
+        // Every field has a TOML representation, so this cannot fail
+        toml::to_string(self).unwrap_or_default()
+    }
+
+    // Prints the resolved config and keeps a copy of it in the output directory, so every run records what it was run with
+    pub fn echo(&self) -> Result<()> {
+        let contents = self.to_toml();
+        println!("Run config:\n{}", contents);
+        if let Some(output_directory) = &self.output_directory {
+            fs::create_dir_all(output_directory)?;
+            fs::write(Path::new(output_directory).join("run_config.toml"), contents)?;
+        }
+        Ok(())
+    }
+
+    pub fn manifest(&self) -> Result<&str> {
+        self.manifest.as_deref().ok_or_else(|| invalid_input("No manifest given, use --manifest or set manifest in the config file".to_string()))
+    }
+
+    pub fn idat_directory(&self) -> Result<&str> {
+        self.idat_directory.as_deref().ok_or_else(|| {
+            invalid_input("No IDAT directory given, use --idat-dir or set idat_directory in the config file".to_string())
+        })
+    }
+
+    pub fn output_directory(&self) -> Result<&str> {
+        self.output_directory.as_deref().ok_or_else(|| {
+            invalid_input("No output directory given, use --output-dir or set output_directory in the config file".to_string())
+        })
+    }
+
+    // Checks what every subcommand running the pipeline needs, so that a bad config fails before any work starts
+    pub fn validate(&self) -> Result<()> {
+        self.manifest()?;
+        self.idat_directory()?;
+        if self.threads == Some(0) {
+            return Err(invalid_input("threads must be at least 1".to_string()));
+        }
+        if !(0.0..=100.0).contains(&self.normalisation.min_match_percentage) {
+            return Err(invalid_input(format!(
+                "min_match_percentage must be between 0 and 100, found {}",
+                self.normalisation.min_match_percentage
+            )));
+        }
+        self.normalisation.fit.validate()?;
+        self.selection()?;
+        Ok(())
+    }
+
+    pub fn selection(&self) -> Result<SampleSelection> {
+        let mut selection = SampleSelection {
+            include: self.selection.samples.clone(),
+            batches: self.selection.batches.clone(),
+            ..Default::default()
+        };
+        if let Some(path) = &self.selection.include_file {
+            selection.include.extend(SampleSelection::read_id_file(path)?);
+        }
+        if let Some(path) = &self.selection.exclude_file {
+            selection.exclude.extend(SampleSelection::read_id_file(path)?);
+        }
+        if let Some(rows) = &self.selection.rows {
+            selection.rows = Some(SampleSelection::parse_rows(rows)?);
+        }
+        Ok(selection)
+    }
+
+    // Columns that are not given keep their Illumina names; without any of them the columns are detected from the sample sheet
+    pub fn column_mapping(&self) -> Option<ColumnMapping> {
+        let columns = &self.columns;
+        if *columns == ColumnsConfig::default() {
+            return None;
+        }
+
+        let illumina = ColumnMapping::illumina();
+        Some(ColumnMapping {
+            sample_id: columns.sample_id.clone().or(illumina.sample_id),
+            sentrix_barcode: columns.sentrix_barcode.clone().unwrap_or(illumina.sentrix_barcode),
+            sentrix_position: columns.sentrix_position.clone().unwrap_or(illumina.sentrix_position),
+            batch: columns.batch.clone(),
+        })
+    }
+}
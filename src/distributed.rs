@@ -0,0 +1,172 @@
+use crate::config::RunConfig;
+use crate::idat_processing;
+use crate::local;
+use mpi::topology::Communicator;
+use mpi::topology::SystemCommunicator;
+use mpi::traits::*;
+use normalisation::apply_normalisation::TransformTable;
+use normalisation::Result;
+use std::ops::Range;
+
+// The master node, it gathers the individuals, hands out the SNPs and writes the results
+const ROOT: i32 = 0;
+
+// Sends rows of X/Y intensities to another node: the number of rows, then every row flattened to x, y, x, y, ...
+fn send_rows<P: Destination>(process: &P, rows: &[Vec<(f64, f64)>]) {
+    process.send(&(rows.len() as u64));
+    for row in rows {
+        let flattened: Vec<f64> = row.iter().flat_map(|&(x, y)| [x, y]).collect();
+        process.send(&flattened[..]);
+    }
+}
+
+// Receives the rows another node sent with send_rows
+fn receive_rows<P: Source>(process: &P) -> Vec<Vec<(f64, f64)>> {
+    let (count, _) = process.receive::<u64>();
+    (0..count)
+        .map(|_| {
+            let (flattened, _) = process.receive_vec::<f64>();
+            flattened.chunks_exact(2).map(|pair| (pair[0], pair[1])).collect()
+        })
+        .collect()
+}
+
+// The master node collects the samples and individuals of every node after its own, in rank order
+// The other nodes are left with none
+fn gather_individuals(world: &SystemCommunicator, rank: i32, size: i32, samples: &mut Vec<String>, individuals: &mut Vec<Vec<(f64, f64)>>) {
+    if rank != ROOT {
+        let root = world.process_at_rank(ROOT);
+        root.send(&(samples.len() as u64));
+        for sample in samples.drain(..) {
+            root.send(sample.as_bytes());
+        }
+        send_rows(&root, individuals);
+        individuals.clear();
+        return;
+    }
+
+    for node in 1..size {
+        let process = world.process_at_rank(node);
+        let (count, _) = process.receive::<u64>();
+        for _ in 0..count {
+            let (sample, _) = process.receive_vec::<u8>();
+            samples.push(String::from_utf8_lossy(&sample).into_owned());
+        }
+        individuals.extend(receive_rows(&process));
+    }
+}
+
+// The master node collects the transforms of every node after its own, sent as the TSV the table is written as,
+// which reads back bit for bit. The other nodes are left with none
+fn gather_transforms(world: &SystemCommunicator, rank: i32, size: i32, transforms: &mut TransformTable) -> Result<()> {
+    if rank != ROOT {
+        let mut tsv = Vec::new();
+        transforms.write_tsv_to(&mut tsv)?;
+        world.process_at_rank(ROOT).send(&tsv[..]);
+        transforms.records.clear();
+        return Ok(());
+    }
+
+    for node in 1..size {
+        let (tsv, _) = world.process_at_rank(node).receive_vec::<u8>();
+        let table = TransformTable::from_tsv(&String::from_utf8_lossy(&tsv))?;
+        transforms.records.extend(table.records);
+    }
+    Ok(())
+}
+
+// The SNPs a node normalises within SNP, contiguous blocks of about the same size on every node
+fn snp_block(snp_count: usize, rank: i32, size: i32) -> Range<usize> {
+    let (rank, size) = (rank as usize, size as usize);
+    snp_count * rank / size..snp_count * (rank + 1) / size
+}
+
+// The master node sends every other node its block of the SNPs and keeps its own
+fn scatter_snps(world: &SystemCommunicator, rank: i32, size: i32, snps: Vec<Vec<(f64, f64)>>) -> Vec<Vec<(f64, f64)>> {
+    if rank != ROOT {
+        return receive_rows(&world.process_at_rank(ROOT));
+    }
+
+    for node in 1..size {
+        send_rows(&world.process_at_rank(node), &snps[snp_block(snps.len(), node, size)]);
+    }
+    snps[snp_block(snps.len(), ROOT, size)].to_vec()
+}
+
+// The master node puts the blocks of SNPs back together in their original order, the other nodes are left with none
+fn gather_snps(world: &SystemCommunicator, rank: i32, size: i32, block: Vec<Vec<(f64, f64)>>) -> Vec<Vec<(f64, f64)>> {
+    if rank != ROOT {
+        send_rows(&world.process_at_rank(ROOT), &block);
+        return Vec::new();
+    }
+
+    let mut snps = block;
+    for node in 1..size {
+        snps.extend(receive_rows(&world.process_at_rank(node)));
+    }
+    snps
+}
+
+// Every node normalises its share of the individuals within BeadSetIDs
+// The master node then gathers the transforms and the individuals, writes the transforms of the whole run
+// and spreads the SNPs across the nodes for the within-SNP stage. It writes the intensities of every individual
+// once the normalised SNPs are back
+fn run_nodes(world: &SystemCommunicator, rank: i32, size: i32, config: &RunConfig) -> Result<()> {
+    let mut number_of_individuals: i32 = 0;
+    let mut normalised = idat_processing::main_processing(rank, size, &mut number_of_individuals, config, None)?;
+    println!(
+        "Node {}: Normalised {} of {} individuals within BeadSetIDs",
+        rank, normalised.individuals.len(), number_of_individuals
+    );
+
+    // One table for the run, as the single process writes, so that apply can read it back
+    gather_transforms(world, rank, size, &mut normalised.transforms)?;
+    if rank == ROOT {
+        normalised.transforms.sort();
+        idat_processing::write_transforms(&normalised.transforms, config, "transforms")?;
+    }
+
+    // The within-SNP stage takes every individual of one SNP at a time
+    gather_individuals(world, rank, size, &mut normalised.samples, &mut normalised.individuals);
+    let snps = if rank == ROOT { idat_processing::transpose_individuals(&normalised.individuals)? } else { Vec::new() };
+
+    let block = scatter_snps(world, rank, size, snps);
+    let block = idat_processing::snp_normalisation(&block, &config.normalisation.fit)?;
+    let snps = gather_snps(world, rank, size, block);
+
+    if rank == ROOT {
+        println!("Normalised {} SNPs across {} individuals", snps.len(), normalised.individuals.len());
+
+        // Turned back into every SNP of each individual to write them out
+        let individuals = idat_processing::transpose_individuals(&snps)?;
+        idat_processing::write_individuals(&normalised.samples, &individuals, &normalised.layout, config)?;
+    }
+    Ok(())
+}
+
+// Runs the pipeline across the MPI processes started by mpirun
+pub fn run(config: &RunConfig) {
+
+    let universe = mpi::initialize().unwrap();
+    let world = universe.world();
+    let size = world.size();
+    let rank = world.rank();
+
+    // The settings the run resolved to are echoed once, by the master node
+    if rank == ROOT {
+        if let Err(err) = config.echo() {
+            eprintln!("Node {}: Error: {}", rank, err);
+            world.abort(1);
+        }
+    }
+
+    // A single process runs the pipeline without any communication
+    let result = if size == 1 { local::run(config) } else { run_nodes(&world, rank, size, config) };
+    if let Err(err) = result {
+        eprintln!("Node {}: Error: {}", rank, err);
+        // The other nodes may be waiting on this one, so the whole job is stopped
+        world.abort(1);
+    }
+
+    println!("Program Finished Running Rank {}", rank);
+}
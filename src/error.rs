@@ -0,0 +1,159 @@
+use crate::idat::ChannelMismatch;
+use std::error;
+use std::fmt;
+use std::io;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// Everything that can go wrong reading the inputs of a run or normalising them
+// Readers leave the path out and the function that opened the file fills it in with in_file,
+// the pipeline then wraps the errors of an individual with for_sample
+#[derive(Debug)]
+pub enum Error {
+    Io { path: Option<String>, source: io::Error },
+    MissingIdat { path: String },
+    BadMagic { path: Option<String>, format: &'static str, found: Vec<u8> },
+    UnsupportedVersion { path: Option<String>, format: &'static str, version: u64 },
+    MissingFieldCode { path: Option<String>, code: u16 },
+    ManifestColumnMissing { path: Option<String>, column: String },
+    ChannelMismatch(ChannelMismatch),
+    DegenerateBeadset { bead_set_id: i32, points: usize },
+    DegenerateSnp { snp: usize, points: usize }, // snp is the position of the SNP in the SNP order of the individuals
+    TooFewPoints { points: usize, required: usize }, // Raised by the stages, which do not know the group they normalise
+    MissingTransform { sample: String, bead_set_id: i32 },
+    MissingSample { sample: String }, // An individual the stored transform table has no transforms for
+    InvalidData { path: Option<String>, message: String },
+    InvalidInput(String),
+    Sample { sample: String, source: Box<Error> },
+}
+
+pub(crate) fn invalid_data(message: String) -> Error {
+    Error::InvalidData { path: None, message }
+}
+
+pub fn invalid_input(message: String) -> Error {
+    Error::InvalidInput(message)
+}
+
+impl Error {
+
+    // Attaches the file the error was raised in, unless it already names one
+    pub fn in_file(mut self, file: &str) -> Error {
+        match &mut self {
+            Error::Io { path, .. }
+            | Error::BadMagic { path, .. }
+            | Error::UnsupportedVersion { path, .. }
+            | Error::MissingFieldCode { path, .. }
+            | Error::ManifestColumnMissing { path, .. }
+            | Error::InvalidData { path, .. } => {
+                path.get_or_insert_with(|| file.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    // Attaches the individual the error was raised for, channel mismatches and missing transforms already name it
+    pub fn for_sample(self, sample: &str) -> Error {
+        match self {
+            Error::Sample { .. } | Error::ChannelMismatch(_) | Error::MissingTransform { .. } | Error::MissingSample { .. } => self,
+            error => Error::Sample { sample: sample.to_string(), source: Box::new(error) },
+        }
+    }
+
+    // The individual the error was raised for, if any
+    pub fn sample(&self) -> Option<&str> {
+        match self {
+            Error::Sample { sample, .. } | Error::MissingTransform { sample, .. } | Error::MissingSample { sample } => Some(sample),
+            Error::ChannelMismatch(
+                ChannelMismatch::MarkerCount { sample, .. }
+                | ChannelMismatch::IlluminaIds { sample, .. }
+                | ChannelMismatch::Barcode { sample, .. }
+                | ChannelMismatch::SentrixPosition { sample, .. },
+            ) => Some(sample),
+            _ => None,
+        }
+    }
+}
+
+fn write_path(f: &mut fmt::Formatter<'_>, path: &Option<String>) -> fmt::Result {
+    match path {
+        Some(path) => write!(f, "{}: ", path),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write_path(f, path)?;
+                write!(f, "{}", source)
+            }
+            Error::MissingIdat { path } => write!(f, "IDAT file not found: {} (nor a gzip-compressed copy)", path),
+            Error::BadMagic { path, format, found } => {
+                write_path(f, path)?;
+                write!(f, "not a valid {} file, magic number is {:?}", format, found)
+            }
+            Error::UnsupportedVersion { path, format, version } => {
+                write_path(f, path)?;
+                write!(f, "{} version {} is not supported", format, version)
+            }
+            Error::MissingFieldCode { path, code } => {
+                write_path(f, path)?;
+                write!(f, "IDAT file is missing field code {}", code)
+            }
+            Error::ManifestColumnMissing { path, column } => {
+                write_path(f, path)?;
+                write!(f, "Manifest [Assay] section is missing the {} column", column)
+            }
+            Error::ChannelMismatch(mismatch) => write!(f, "{}", mismatch),
+            Error::DegenerateBeadset { bead_set_id, points } => write!(
+                f,
+                "BeadSetID {} is degenerate, {} points are left after removing outliers",
+                bead_set_id, points
+            ),
+            Error::DegenerateSnp { snp, points } => write!(
+                f,
+                "SNP {} is degenerate, {} individuals are left after removing outliers",
+                snp, points
+            ),
+            Error::TooFewPoints { points, required } => {
+                write!(f, "{} points are too few to normalise, at least {} are needed", points, required)
+            }
+            Error::MissingTransform { sample, bead_set_id } => {
+                write!(f, "No stored transform for BeadSetID {} of sample {}", bead_set_id, sample)
+            }
+            Error::MissingSample { sample } => write!(f, "Sample {} is not in the stored transform table", sample),
+            Error::InvalidData { path, message } => {
+                write_path(f, path)?;
+                write!(f, "{}", message)
+            }
+            Error::InvalidInput(message) => write!(f, "{}", message),
+            Error::Sample { sample, source } => write!(f, "Sample {}: {}", sample, source),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::ChannelMismatch(mismatch) => Some(mismatch),
+            Error::Sample { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io { path: None, source: err }
+    }
+}
+
+impl From<ChannelMismatch> for Error {
+    fn from(mismatch: ChannelMismatch) -> Error {
+        Error::ChannelMismatch(mismatch)
+    }
+}
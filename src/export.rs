@@ -0,0 +1,90 @@
+use crate::config::RunConfig;
+use crate::idat_processing::{self, load_samples, process_sample_record, SampleData};
+use crate::local;
+use normalisation::manifest::ProbeLayout;
+use normalisation::{Error, Result};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Writes the X/Y intensities of every layout SNP the sample has, one SNP per line
+fn write_sample(path: &Path, sample: &SampleData, layout: &ProbeLayout) -> io::Result<()> {
+    let positions = if layout.matches_ids(&sample.ids) {
+        layout.bead_positions.clone()
+    } else {
+        layout.locate_in(&sample.ids).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "the IDAT is missing SNPs of the probe layout")
+        })?
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "SNP\tX\tY")?;
+    for &(probe_index, bead_a, bead_b) in &positions {
+        let probe = &layout.probes[probe_index];
+        if let Some((x, y)) = probe.intensities(sample.data[bead_a], bead_b.map(|index| sample.data[index])) {
+            writeln!(writer, "{}\t{}\t{}", probe.name, x, y)?;
+        }
+    }
+    writer.flush()
+}
+
+// Normalises every sample as normalise does in a single process and writes the intensities, but not the transforms
+fn export_normalised(config: &RunConfig) -> Result<()> {
+    config.output_directory()?;
+    let normalised = local::normalise(config)?;
+    idat_processing::write_individuals(&normalised.samples, &normalised.individuals, &normalised.layout, config)
+}
+
+// Entry point of the export subcommand, returns the process exit code
+// Each sample is written to <output directory>/<sample>.tsv, with the raw intensities unless normalised is set
+pub fn run(config: &RunConfig, normalised: bool) -> i32 {
+    if normalised {
+        return crate::exit_code(export_normalised(config));
+    }
+
+    let setup = || -> Result<_> {
+        let output_directory = config.output_directory()?;
+        fs::create_dir_all(output_directory)?;
+        let (sample_sheet, idat_template) = load_samples(config, 0)?;
+        Ok((output_directory, config.idat_directory()?, config.manifest()?, sample_sheet, idat_template))
+    };
+    let (output_directory, idat_directory, manifest, sample_sheet, idat_template) = match setup() {
+        Ok(setup) => setup,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return 1;
+        }
+    };
+
+    // The probe layout is built from the first readable sample, or loaded from the cache next to the manifest
+    let mut layout: Option<ProbeLayout> = None;
+    let mut failures = Vec::new();
+    for record in &sample_sheet.records {
+        let sample = match process_sample_record(record, idat_directory, &idat_template, 0, 1) {
+            Ok(sample) => sample,
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+
+        let layout = match &layout {
+            Some(layout) => layout,
+            None => match ProbeLayout::load_or_build(manifest, &sample.ids, &sample.chip_type) {
+                Ok(built) => layout.insert(built),
+                Err(err) => {
+                    eprintln!("Error building the probe layout: {}", err);
+                    return 1;
+                }
+            },
+        };
+
+        let path = Path::new(output_directory).join(format!("{}.tsv", record.sample_id));
+        match write_sample(&path, &sample, layout) {
+            Ok(()) => println!("Exported {}", path.display()),
+            Err(err) => failures.push(Error::from(err).in_file(&path.to_string_lossy()).for_sample(&record.sample_id)),
+        }
+    }
+
+    crate::summarise_samples(sample_sheet.records.len(), &failures, "exported", "not exported")
+}
@@ -0,0 +1,208 @@
+use super::{
+    read_header, read_idat_string, IdatFile, FID_BARCODE, FID_CHIP_TYPE, FID_ILLUMINAID,
+    FID_MEAN, FID_N_SNPS_READ, FID_SENTRIX_POSITION,
+};
+use crate::error::{invalid_data, Error, Result};
+use flate2::read::MultiGzDecoder;
+use memmap::Mmap;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read};
+use std::ops::Deref;
+
+// First two bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Magic number, version and field count, the shortest an IDAT file can be (and shorter than any gzip stream)
+const HEADER_LENGTH: u64 = 16;
+
+// The bytes of an IDAT file: mapped directly for plain files, decompressed into memory for gzip files
+pub enum IdatBytes {
+    Mapped(Mmap),
+    Decompressed(Vec<u8>),
+}
+
+impl Deref for IdatBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            IdatBytes::Mapped(mmap) => mmap,
+            IdatBytes::Decompressed(buffer) => buffer,
+        }
+    }
+}
+
+impl IdatBytes {
+
+    // Compressed files are recognised by the ".gz" extension or by the gzip magic bytes
+    pub fn load(path: &str) -> Result<IdatBytes> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+
+        // Checked before mapping, an empty file cannot be mapped and a truncated one has no header to read
+        let length = file.metadata().map_err(|err| Error::from(err).in_file(path))?.len();
+        if length < HEADER_LENGTH {
+            return Err(invalid_data(format!("IDAT file is {} bytes, shorter than the {}-byte header", length, HEADER_LENGTH)).in_file(path));
+        }
+
+        Self::load_file(&file, path.ends_with(".gz")).map_err(|err| Error::from(err).in_file(path))
+    }
+
+    fn load_file(file: &File, compressed: bool) -> io::Result<IdatBytes> {
+        // Safety: the IDAT files are treated as read-only inputs for the lifetime of the mapping
+        let mmap = unsafe { Mmap::map(file)? };
+
+        if compressed || mmap.starts_with(&GZIP_MAGIC) {
+            let mut buffer = Vec::with_capacity(mmap.len() * 4);
+            MultiGzDecoder::new(&mmap[..]).read_to_end(&mut buffer)?;
+            Ok(IdatBytes::Decompressed(buffer))
+        } else {
+            Ok(IdatBytes::Mapped(mmap))
+        }
+    }
+}
+
+// Returns the path of an IDAT file, falling back to the gzip-compressed copy when the plain file does not exist
+pub fn resolve_idat_path(path: &str) -> Option<String> {
+    if fs::metadata(path).is_ok() {
+        return Some(path.to_string());
+    }
+
+    let compressed = format!("{}.gz", path);
+    if fs::metadata(&compressed).is_ok() {
+        return Some(compressed);
+    }
+
+    None
+}
+
+// An IDAT file held in memory. Only the header is decoded when the file is opened,
+// the ID and mean arrays are borrowed straight from the bytes and converted on access
+pub struct MappedIdat {
+    bytes: IdatBytes,
+    version: u64,
+    field_offsets: Vec<(u16, u64)>,
+    num_markers: usize,
+    ids_offset: usize,
+    means_offset: usize,
+}
+
+impl MappedIdat {
+
+    pub fn open(path: &str) -> Result<MappedIdat> {
+        Self::from_bytes(IdatBytes::load(path)?).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_bytes(storage: IdatBytes) -> Result<MappedIdat> {
+        let bytes: &[u8] = &storage;
+        let (version, field_offsets) = read_header(&mut Cursor::new(bytes))?;
+
+        let offset_of = |code: u16| {
+            field_offsets
+                .iter()
+                .find(|&&(fcode, _)| fcode == code)
+                .map(|&(_, offset)| offset as usize)
+                .ok_or(Error::MissingFieldCode { path: None, code })
+        };
+
+        let markers_offset = offset_of(FID_N_SNPS_READ)?;
+        let num_markers = Self::checked_slice(bytes, markers_offset, 4)
+            .map(|raw| u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize)?;
+
+        // Check the arrays lie inside the file so the accessors below cannot go out of bounds
+        let ids_offset = offset_of(FID_ILLUMINAID)?;
+        Self::checked_slice(bytes, ids_offset, num_markers.saturating_mul(4))?;
+        let means_offset = offset_of(FID_MEAN)?;
+        Self::checked_slice(bytes, means_offset, num_markers.saturating_mul(2))?;
+
+        Ok(MappedIdat {
+            bytes: storage,
+            version,
+            field_offsets,
+            num_markers,
+            ids_offset,
+            means_offset,
+        })
+    }
+
+    fn checked_slice(bytes: &[u8], offset: usize, length: usize) -> Result<&[u8]> {
+        // The offset comes from the file, so the end of the field may not even be a valid index
+        let end = offset.checked_add(length);
+        end.and_then(|end| bytes.get(offset..end)).ok_or_else(|| {
+            invalid_data(format!(
+                "IDAT field at offset {} with length {} runs past the end of the file ({} bytes)",
+                offset,
+                length,
+                bytes.len()
+            ))
+        })
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn field_offsets(&self) -> &[(u16, u64)] {
+        &self.field_offsets
+    }
+
+    pub fn num_markers(&self) -> usize {
+        self.num_markers
+    }
+
+    // The Illumina ID array exactly as it is stored in the file (little-endian u32)
+    pub fn raw_ids(&self) -> &[u8] {
+        &self.bytes[self.ids_offset..self.ids_offset + self.num_markers * 4]
+    }
+
+    // The mean array exactly as it is stored in the file (little-endian u16)
+    pub fn raw_means(&self) -> &[u8] {
+        &self.bytes[self.means_offset..self.means_offset + self.num_markers * 2]
+    }
+
+    pub fn ids(&self) -> impl ExactSizeIterator<Item = u32> + '_ {
+        self.raw_ids()
+            .chunks_exact(4)
+            .map(|raw| u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]))
+    }
+
+    pub fn means(&self) -> impl ExactSizeIterator<Item = u16> + '_ {
+        self.raw_means()
+            .chunks_exact(2)
+            .map(|raw| u16::from_le_bytes([raw[0], raw[1]]))
+    }
+
+    pub fn id(&self, index: usize) -> u32 {
+        let start = self.ids_offset + index * 4;
+        let raw = &self.bytes[start..start + 4];
+        u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]])
+    }
+
+    pub fn mean(&self, index: usize) -> u16 {
+        let start = self.means_offset + index * 2;
+        u16::from_le_bytes([self.bytes[start], self.bytes[start + 1]])
+    }
+
+    // Decodes a string field on demand, fields that are absent or unreadable give None
+    pub fn string_field(&self, code: u16) -> Option<String> {
+        let offset = self.field_offsets.iter().find(|&&(fcode, _)| fcode == code)?.1 as usize;
+        let mut cursor = Cursor::new(self.bytes.get(offset..)?);
+        read_idat_string(&mut cursor).ok()
+    }
+
+    pub fn barcode(&self) -> Option<String> {
+        self.string_field(FID_BARCODE)
+    }
+
+    pub fn chip_type(&self) -> Option<String> {
+        self.string_field(FID_CHIP_TYPE)
+    }
+
+    pub fn sentrix_position(&self) -> Option<String> {
+        self.string_field(FID_SENTRIX_POSITION)
+    }
+
+    // Decodes every field into an owned IdatFile, for when more than IDs and means are needed
+    pub fn decode(&self) -> Result<IdatFile> {
+        IdatFile::from_reader(&mut Cursor::new(&self.bytes[..]))
+    }
+}
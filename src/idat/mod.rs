@@ -0,0 +1,255 @@
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+use std::collections::HashMap;
+use crate::error::{invalid_data, Error, Result};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+mod mapped;
+mod pairing;
+mod summary;
+mod writer;
+pub use mapped::{resolve_idat_path, IdatBytes, MappedIdat};
+pub use pairing::{validate_channel_pair, ChannelMismatch};
+pub use summary::{ArrayStatistics, IdatSummary};
+pub use writer::write_idat_string;
+
+// Field codes of the IDAT v3 field table
+pub const FID_N_SNPS_READ: u16 = 1000;
+pub const FID_ILLUMINAID: u16 = 102;
+pub const FID_SD: u16 = 103;
+pub const FID_MEAN: u16 = 104;
+pub const FID_NBEADS: u16 = 107;
+pub const FID_MID_BLOCK: u16 = 200;
+pub const FID_RUN_INFO: u16 = 300;
+pub const FID_RED_GREEN: u16 = 400;
+pub const FID_MANIFEST: u16 = 401;
+pub const FID_BARCODE: u16 = 402;
+pub const FID_CHIP_TYPE: u16 = 403;
+pub const FID_SENTRIX_POSITION: u16 = 404;
+
+// String fields without a documented meaning, kept so that nothing in the file is lost
+pub const FID_UNKNOWN_STRINGS: [u16; 7] = [405, 406, 407, 408, 409, 410, 510];
+
+pub const IDAT_MAGIC: &[u8; 4] = b"IDAT";
+pub const IDAT_VERSION: u64 = 3;
+
+// One record of the run info block (scan and decode steps the chip went through)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunInfo {
+    pub run_time: String,
+    pub block_type: String,
+    pub block_pars: String,
+    pub block_code: String,
+    pub code_version: String,
+}
+
+// Every field of an IDAT v3 file, decoded into native types
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdatFile {
+    pub version: u64,
+    pub field_offsets: Vec<(u16, u64)>, // The field table in the order it appears in the file
+    pub num_markers: u32,
+    pub illumina_ids: Vec<u32>,
+    pub std_devs: Vec<u16>,
+    pub means: Vec<u16>,
+    pub n_beads: Vec<u8>,
+    pub mid_block: Vec<u32>,
+    pub red_green: Option<u32>,
+    pub manifest: Option<String>,
+    pub barcode: Option<String>,
+    pub chip_type: Option<String>,
+    pub sentrix_position: Option<String>,
+    pub run_info: Vec<RunInfo>,
+    pub unknown_strings: Vec<(u16, String)>,
+}
+
+// Strings are stored with a 7-bit variable length prefix followed by the bytes
+pub fn read_idat_string<R: Read>(reader: &mut R) -> Result<String> {
+    let mut length: usize = 0;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        length |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift > 28 {
+            return Err(invalid_data("IDAT string length prefix is too long".to_string()));
+        }
+    }
+
+    // Read as the bytes arrive rather than allocating a corrupt length up front, so at most what is left is read
+    let mut buffer = Vec::new();
+    reader.take(length as u64).read_to_end(&mut buffer)?;
+    if buffer.len() < length {
+        return Err(invalid_data(format!("IDAT string of {} bytes is cut short after {}", length, buffer.len())));
+    }
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+// Reads the magic number, version and field table at the start of every IDAT file
+pub fn read_header<R: Read>(reader: &mut R) -> Result<(u64, Vec<(u16, u64)>)> {
+    let mut magic_number = [0u8; 4];
+    reader.read_exact(&mut magic_number)?;
+
+    if magic_number != IDAT_MAGIC[..] {
+        return Err(Error::BadMagic { path: None, format: "IDAT", found: magic_number.to_vec() });
+    }
+
+    // Read the IDAT version (a 64-bit integer in little-endian byte order)
+    let version = reader.read_u64::<LittleEndian>()?;
+
+    if version != IDAT_VERSION {
+        return Err(Error::UnsupportedVersion { path: None, format: "IDAT", version });
+    }
+
+    // Read the field table
+    let fcount = reader.read_u32::<LittleEndian>()?;
+    // Not preallocated, a corrupt count fails at the end of the file instead
+    let mut field_offsets = Vec::new();
+    for _ in 0..fcount {
+        let fcode = reader.read_u16::<LittleEndian>()?;
+        let offset = reader.read_u64::<LittleEndian>()?;
+        field_offsets.push((fcode, offset));
+    }
+
+    Ok((version, field_offsets))
+}
+
+// Fails when fewer than count values of width bytes are left in the stream, before anything is allocated for them
+// Counts come from the file, so a corrupt one would otherwise allocate gigabytes before the read fails
+pub(crate) fn check_remaining<R: Seek>(reader: &mut R, count: usize, width: u64, field: &str) -> Result<()> {
+    let position = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(position))?;
+
+    let needed = (count as u64).saturating_mul(width);
+    let remaining = end.saturating_sub(position);
+    if needed > remaining {
+        return Err(invalid_data(format!("{} of {} values needs {} bytes but only {} are left", field, count, needed, remaining)));
+    }
+    Ok(())
+}
+
+fn read_u32_array<R: Read + Seek>(reader: &mut R, count: usize, field: &str) -> Result<Vec<u32>> {
+    check_remaining(reader, count, 4, field)?;
+    let mut buffer = vec![0u32; count];
+    reader.read_u32_into::<LittleEndian>(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u16_array<R: Read + Seek>(reader: &mut R, count: usize, field: &str) -> Result<Vec<u16>> {
+    check_remaining(reader, count, 2, field)?;
+    let mut buffer = vec![0u16; count];
+    reader.read_u16_into::<LittleEndian>(&mut buffer)?;
+    Ok(buffer)
+}
+
+impl IdatFile {
+
+    // Reads and decodes an IDAT file from disk, gzip-compressed or not
+    pub fn read(path: &str) -> Result<IdatFile> {
+        let bytes = IdatBytes::load(path)?;
+        Self::from_reader(&mut Cursor::new(&bytes[..])).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<IdatFile> {
+        let (version, field_offsets) = read_header(reader)?;
+        let field_val: HashMap<u16, u64> = field_offsets.iter().cloned().collect();
+
+        let required = |code: u16| {
+            field_val.get(&code).copied().ok_or(Error::MissingFieldCode { path: None, code })
+        };
+
+        let mut idat = IdatFile {
+            version,
+            ..Default::default()
+        };
+
+        // The number of markers determines the length of every per-probe array
+        reader.seek(SeekFrom::Start(required(FID_N_SNPS_READ)?))?;
+        idat.num_markers = reader.read_u32::<LittleEndian>()?;
+        let count = idat.num_markers as usize;
+
+        reader.seek(SeekFrom::Start(required(FID_ILLUMINAID)?))?;
+        idat.illumina_ids = read_u32_array(reader, count, "Illumina ID array")?;
+
+        reader.seek(SeekFrom::Start(required(FID_MEAN)?))?;
+        idat.means = read_u16_array(reader, count, "Mean array")?;
+
+        if let Some(&offset) = field_val.get(&FID_SD) {
+            reader.seek(SeekFrom::Start(offset))?;
+            idat.std_devs = read_u16_array(reader, count, "Standard deviation array")?;
+        }
+
+        if let Some(&offset) = field_val.get(&FID_NBEADS) {
+            reader.seek(SeekFrom::Start(offset))?;
+            check_remaining(reader, count, 1, "Bead count array")?;
+            let mut n_beads = vec![0u8; count];
+            reader.read_exact(&mut n_beads)?;
+            idat.n_beads = n_beads;
+        }
+
+        if let Some(&offset) = field_val.get(&FID_MID_BLOCK) {
+            reader.seek(SeekFrom::Start(offset))?;
+            let mid_count = reader.read_u32::<LittleEndian>()?;
+            idat.mid_block = read_u32_array(reader, mid_count as usize, "Mid block")?;
+        }
+
+        if let Some(&offset) = field_val.get(&FID_RUN_INFO) {
+            reader.seek(SeekFrom::Start(offset))?;
+            let run_count = reader.read_u32::<LittleEndian>()?;
+            for _ in 0..run_count {
+                idat.run_info.push(RunInfo {
+                    run_time: read_idat_string(reader)?,
+                    block_type: read_idat_string(reader)?,
+                    block_pars: read_idat_string(reader)?,
+                    block_code: read_idat_string(reader)?,
+                    code_version: read_idat_string(reader)?,
+                });
+            }
+        }
+
+        if let Some(&offset) = field_val.get(&FID_RED_GREEN) {
+            reader.seek(SeekFrom::Start(offset))?;
+            idat.red_green = Some(reader.read_u32::<LittleEndian>()?);
+        }
+
+        // Optional string fields
+        let mut read_string = |code: u16| -> Result<Option<String>> {
+            match field_val.get(&code) {
+                Some(&offset) => {
+                    reader.seek(SeekFrom::Start(offset))?;
+                    Ok(Some(read_idat_string(reader)?))
+                }
+                None => Ok(None),
+            }
+        };
+
+        idat.manifest = read_string(FID_MANIFEST)?;
+        idat.barcode = read_string(FID_BARCODE)?;
+        idat.chip_type = read_string(FID_CHIP_TYPE)?;
+        idat.sentrix_position = read_string(FID_SENTRIX_POSITION)?;
+
+        for code in FID_UNKNOWN_STRINGS {
+            if let Some(value) = read_string(code)? {
+                idat.unknown_strings.push((code, value));
+            }
+        }
+
+        idat.field_offsets = field_offsets;
+
+        Ok(idat)
+    }
+
+    // Looks up the offset of a field code in the field table
+    pub fn field_offset(&self, code: u16) -> Option<u64> {
+        self.field_offsets.iter().find(|&&(fcode, _)| fcode == code).map(|&(_, offset)| offset)
+    }
+
+    // Mean intensities widened to f64, the form the normalisation stages work on
+    pub fn means_f64(&self) -> Vec<f64> {
+        self.means.iter().map(|&value| value as f64).collect()
+    }
+}
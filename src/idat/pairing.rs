@@ -0,0 +1,84 @@
+use super::MappedIdat;
+use std::error::Error;
+use std::fmt;
+
+// Reasons the Red and Grn IDAT files given for a sample cannot be paired probe by probe
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelMismatch {
+    MarkerCount { sample: String, red: usize, green: usize },
+    IlluminaIds { sample: String, index: usize, red: u32, green: u32 },
+    Barcode { sample: String, red: Option<String>, green: Option<String> },
+    SentrixPosition { sample: String, red: Option<String>, green: Option<String> },
+}
+
+impl fmt::Display for ChannelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelMismatch::MarkerCount { sample, red, green } => write!(
+                f,
+                "Sample {}: Red IDAT has {} markers but Grn IDAT has {}",
+                sample, red, green
+            ),
+            ChannelMismatch::IlluminaIds { sample, index, red, green } => write!(
+                f,
+                "Sample {}: probe IDs differ at index {} (Red {}, Grn {})",
+                sample, index, red, green
+            ),
+            ChannelMismatch::Barcode { sample, red, green } => write!(
+                f,
+                "Sample {}: Red IDAT barcode {:?} does not match Grn IDAT barcode {:?}",
+                sample, red, green
+            ),
+            ChannelMismatch::SentrixPosition { sample, red, green } => write!(
+                f,
+                "Sample {}: Red IDAT Sentrix position {:?} does not match Grn IDAT Sentrix position {:?}",
+                sample, red, green
+            ),
+        }
+    }
+}
+
+impl Error for ChannelMismatch {}
+
+// Checks that the Red and Grn IDAT files come from the same scan, so their means can be zipped by position
+pub fn validate_channel_pair(sample: &str, red: &MappedIdat, green: &MappedIdat) -> Result<(), ChannelMismatch> {
+    if red.num_markers() != green.num_markers() {
+        return Err(ChannelMismatch::MarkerCount {
+            sample: sample.to_string(),
+            red: red.num_markers(),
+            green: green.num_markers(),
+        });
+    }
+
+    // Compare the raw arrays first and only decode the IDs to report the first difference
+    if red.raw_ids() != green.raw_ids() {
+        if let Some((index, (red_id, green_id))) = red.ids().zip(green.ids()).enumerate().find(|(_, (a, b))| a != b) {
+            return Err(ChannelMismatch::IlluminaIds {
+                sample: sample.to_string(),
+                index,
+                red: red_id,
+                green: green_id,
+            });
+        }
+    }
+
+    let (red_barcode, green_barcode) = (red.barcode(), green.barcode());
+    if red_barcode != green_barcode {
+        return Err(ChannelMismatch::Barcode {
+            sample: sample.to_string(),
+            red: red_barcode,
+            green: green_barcode,
+        });
+    }
+
+    let (red_position, green_position) = (red.sentrix_position(), green.sentrix_position());
+    if red_position != green_position {
+        return Err(ChannelMismatch::SentrixPosition {
+            sample: sample.to_string(),
+            red: red_position,
+            green: green_position,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use super::{IdatFile, RunInfo};
+use serde::Serialize;
+
+// Summary statistics of a per-probe array
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ArrayStatistics {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+}
+
+impl ArrayStatistics {
+
+    pub fn from_values(values: &[f64]) -> ArrayStatistics {
+        if values.is_empty() {
+            return ArrayStatistics::default();
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let variance = sorted.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / count as f64;
+        let median = if count.is_multiple_of(2) {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        } else {
+            sorted[count / 2]
+        };
+
+        ArrayStatistics {
+            count,
+            min: sorted[0],
+            max: sorted[count - 1],
+            mean,
+            median,
+            std_dev: variance.sqrt(),
+        }
+    }
+}
+
+fn widen<T: Copy + Into<f64>>(values: &[T]) -> Vec<f64> {
+    values.iter().map(|&value| value.into()).collect()
+}
+
+// Header fields and statistics of one IDAT file, as reported by the inspect subcommand
+#[derive(Debug, Clone, Serialize)]
+pub struct IdatSummary {
+    pub path: String,
+    pub version: u64,
+    pub field_offsets: Vec<(u16, u64)>,
+    pub barcode: Option<String>,
+    pub chip_type: Option<String>,
+    pub sentrix_position: Option<String>,
+    pub manifest: Option<String>,
+    pub red_green: Option<u32>,
+    pub num_markers: u32,
+    pub means: ArrayStatistics,
+    pub std_devs: ArrayStatistics,
+    pub n_beads: ArrayStatistics,
+    pub zero_bead_probes: usize,
+    pub run_info: Vec<RunInfo>,
+}
+
+impl IdatSummary {
+
+    pub fn from_idat(path: &str, idat: &IdatFile) -> IdatSummary {
+        IdatSummary {
+            path: path.to_string(),
+            version: idat.version,
+            field_offsets: idat.field_offsets.clone(),
+            barcode: idat.barcode.clone(),
+            chip_type: idat.chip_type.clone(),
+            sentrix_position: idat.sentrix_position.clone(),
+            manifest: idat.manifest.clone(),
+            red_green: idat.red_green,
+            num_markers: idat.num_markers,
+            means: ArrayStatistics::from_values(&widen(&idat.means)),
+            std_devs: ArrayStatistics::from_values(&widen(&idat.std_devs)),
+            n_beads: ArrayStatistics::from_values(&widen(&idat.n_beads)),
+            zero_bead_probes: idat.n_beads.iter().filter(|&&beads| beads == 0).count(),
+            run_info: idat.run_info.clone(),
+        }
+    }
+}
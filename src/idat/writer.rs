@@ -0,0 +1,188 @@
+use super::{
+    IdatFile, RunInfo, FID_BARCODE, FID_CHIP_TYPE, FID_ILLUMINAID, FID_MANIFEST, FID_MEAN, FID_MID_BLOCK,
+    FID_NBEADS, FID_N_SNPS_READ, FID_RED_GREEN, FID_RUN_INFO, FID_SD, FID_SENTRIX_POSITION, IDAT_MAGIC,
+    IDAT_VERSION,
+};
+use crate::error::{invalid_input, Error, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+// Size of the magic number, version and field count that precede the field table
+const HEADER_SIZE: u64 = 4 + 8 + 4;
+// Each field table entry is a u16 code followed by a u64 offset
+const FIELD_ENTRY_SIZE: u64 = 2 + 8;
+
+// Strings are written with the same 7-bit variable length prefix the reader expects
+pub fn write_idat_string<W: Write>(writer: &mut W, value: &str) -> io::Result<()> {
+    let mut length = value.len();
+    loop {
+        let byte = (length & 0x7f) as u8;
+        length >>= 7;
+        if length == 0 {
+            writer.write_u8(byte)?;
+            break;
+        }
+        writer.write_u8(byte | 0x80)?;
+    }
+    writer.write_all(value.as_bytes())
+}
+
+impl IdatFile {
+
+    // A minimal file holding only probe IDs and means, the starting point for synthetic fixtures
+    pub fn new(illumina_ids: Vec<u32>, means: Vec<u16>) -> IdatFile {
+        IdatFile {
+            version: IDAT_VERSION,
+            num_markers: illumina_ids.len() as u32,
+            illumina_ids,
+            means,
+            ..Default::default()
+        }
+    }
+
+    // Serialises the fields into the body of the file, returning each field code with its body
+    fn encode_fields(&self) -> Result<Vec<(u16, Vec<u8>)>> {
+        let count = self.num_markers as usize;
+        let check_length = |name: &str, length: usize| {
+            if length != count {
+                return Err(invalid_input(format!(
+                    "IDAT {} array has {} entries but num_markers is {}",
+                    name, length, count
+                )));
+            }
+            Ok(())
+        };
+
+        check_length("Illumina ID", self.illumina_ids.len())?;
+        check_length("mean", self.means.len())?;
+
+        let mut fields: Vec<(u16, Vec<u8>)> = Vec::new();
+
+        let mut body = Vec::new();
+        body.write_u32::<LittleEndian>(self.num_markers)?;
+        fields.push((FID_N_SNPS_READ, body));
+
+        let mut body = Vec::with_capacity(count * 4);
+        for &id in &self.illumina_ids {
+            body.write_u32::<LittleEndian>(id)?;
+        }
+        fields.push((FID_ILLUMINAID, body));
+
+        if !self.std_devs.is_empty() {
+            check_length("standard deviation", self.std_devs.len())?;
+            let mut body = Vec::with_capacity(count * 2);
+            for &sd in &self.std_devs {
+                body.write_u16::<LittleEndian>(sd)?;
+            }
+            fields.push((FID_SD, body));
+        }
+
+        let mut body = Vec::with_capacity(count * 2);
+        for &mean in &self.means {
+            body.write_u16::<LittleEndian>(mean)?;
+        }
+        fields.push((FID_MEAN, body));
+
+        if !self.n_beads.is_empty() {
+            check_length("bead count", self.n_beads.len())?;
+            fields.push((FID_NBEADS, self.n_beads.clone()));
+        }
+
+        if !self.mid_block.is_empty() {
+            let mut body = Vec::with_capacity(4 + self.mid_block.len() * 4);
+            body.write_u32::<LittleEndian>(self.mid_block.len() as u32)?;
+            for &value in &self.mid_block {
+                body.write_u32::<LittleEndian>(value)?;
+            }
+            fields.push((FID_MID_BLOCK, body));
+        }
+
+        if !self.run_info.is_empty() {
+            let mut body = Vec::new();
+            body.write_u32::<LittleEndian>(self.run_info.len() as u32)?;
+            for RunInfo { run_time, block_type, block_pars, block_code, code_version } in &self.run_info {
+                for value in [run_time, block_type, block_pars, block_code, code_version] {
+                    write_idat_string(&mut body, value)?;
+                }
+            }
+            fields.push((FID_RUN_INFO, body));
+        }
+
+        if let Some(red_green) = self.red_green {
+            let mut body = Vec::new();
+            body.write_u32::<LittleEndian>(red_green)?;
+            fields.push((FID_RED_GREEN, body));
+        }
+
+        let strings = [
+            (FID_MANIFEST, &self.manifest),
+            (FID_BARCODE, &self.barcode),
+            (FID_CHIP_TYPE, &self.chip_type),
+            (FID_SENTRIX_POSITION, &self.sentrix_position),
+        ];
+        for (code, value) in strings {
+            if let Some(value) = value {
+                let mut body = Vec::new();
+                write_idat_string(&mut body, value)?;
+                fields.push((code, body));
+            }
+        }
+
+        for (code, value) in &self.unknown_strings {
+            let mut body = Vec::new();
+            write_idat_string(&mut body, value)?;
+            fields.push((*code, body));
+        }
+
+        Ok(fields)
+    }
+
+    // Writes a valid IDAT v3 file and returns the field table that was written
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<Vec<(u16, u64)>> {
+        let fields = self.encode_fields()?;
+
+        // The field bodies follow the field table in the order they are listed
+        let mut offset = HEADER_SIZE + FIELD_ENTRY_SIZE * fields.len() as u64;
+        let mut field_offsets = Vec::with_capacity(fields.len());
+        for (code, body) in &fields {
+            field_offsets.push((*code, offset));
+            offset += body.len() as u64;
+        }
+
+        writer.write_all(IDAT_MAGIC)?;
+        writer.write_u64::<LittleEndian>(IDAT_VERSION)?;
+        writer.write_u32::<LittleEndian>(fields.len() as u32)?;
+        for &(code, offset) in &field_offsets {
+            writer.write_u16::<LittleEndian>(code)?;
+            writer.write_u64::<LittleEndian>(offset)?;
+        }
+        for (_, body) in &fields {
+            writer.write_all(body)?;
+        }
+
+        Ok(field_offsets)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.write_to(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    // Writes the file to disk, gzip-compressed when the path ends with ".gz"
+    pub fn write(&self, path: &str) -> Result<Vec<(u16, u64)>> {
+        let file = BufWriter::new(File::create(path).map_err(|err| Error::from(err).in_file(path))?);
+        if path.ends_with(".gz") {
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            let field_offsets = self.write_to(&mut encoder)?;
+            encoder.finish()?.flush()?;
+            Ok(field_offsets)
+        } else {
+            let mut file = file;
+            let field_offsets = self.write_to(&mut file)?;
+            file.flush()?;
+            Ok(field_offsets)
+        }
+    }
+}
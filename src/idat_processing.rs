@@ -1,294 +1,187 @@
 //porting Crates and Modules
-extern crate mpi;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufRead};
-use std::io::Read;
-use byteorder::LittleEndian;
-use byteorder::ReadBytesExt;
-use std::io::Seek;
-use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use std::thread;
-use std::thread::JoinHandle;
-use mpi::topology::SystemCommunicator;
-use std::fs;
-use normalisation::apply_normalisation::Normalise;
-use crate::mpi::collective::CommunicatorCollectives;
-use crate::mpi::topology::Communicator;
-use crate::mpi::point_to_point::Source;
-use crate::mpi::point_to_point::Destination;
+use normalisation::apply_normalisation::{NormalizationConfig, Normalise, TransformRecord, TransformTable};
+use normalisation::idat::{resolve_idat_path, validate_channel_pair, MappedIdat};
+use normalisation::manifest::{assemble_snp_intensities, BeadPosition, CompatibilityReport, ProbeLayout};
+use normalisation::sample_sheet::{discover_samples, Channel, PathTemplate, SampleRecord, SampleSheet, DISCOVERED_TEMPLATE};
+use normalisation::{Error, Result};
+use crate::config::{NormalisationConfig, RunConfig};
 use rayon::prelude::*;
 
-// Initialising Constant Variable and New Data types
-const FID_N_SNPS_READ: u16 = 1000;
-const FID_BARCODE: u16 = 402;
-const FID_ILLUMINAID: u16 = 102;
-const FID_MEAN: u16 = 104;
-
-fn read_u32_array<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<u32>> {
-    let mut buffer = vec![0u32; count];
-    reader.read_u32_into::<LittleEndian>(&mut buffer)?;
-    Ok(buffer)
+// Function maps the idat file of an individual into memory
+// The IDs and means are borrowed from the mapping and only converted when the caller iterates over them
+fn read_idat_values(fname: &str, _illumina_type: &str) -> Result<MappedIdat> {
+    MappedIdat::open(fname)
 }
 
-fn read_u16_array<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<u16>> {
-    let mut buffer = vec![0u16; count];
-    reader.read_u16_into::<LittleEndian>(&mut buffer)?;
-    Ok(buffer)
+// The intensities of an individual together with the probe ids and chip type they were scanned with
+#[derive(Default)]
+pub(crate) struct SampleData {
+    pub(crate) sample: String,
+    pub(crate) chip_type: String,
+    pub(crate) ids: Vec<u32>,
+    pub(crate) data: Vec<(f64, f64)>,
 }
 
-// Function processes the idat files of the individuals
-fn read_idat_values(fname: &str, _illumina_type: &str) -> Result<(Vec<u32>, Vec<f64>),io::Error> {
-    let mut file = File::open(fname)?;
-
-    // Read as a string
-    let mut magic_number = [0u8; 4];
-    file.read_exact(&mut magic_number)?;
-
-    if magic_number != b"IDAT"[..] {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("Magic number is {:?}", magic_number),
-        ));
-    }
-
-    // Read the IDAT version (a 64-bit integer in little-endian byte order)
-    let version = file.read_u64::<LittleEndian>()?;
-
-    if version != 3 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            format!("IDAT version 3 supported only, found {}", version),
-        ));
-    }
+// What to do with a sample whose IDAT does not cover enough of the manifest
+#[derive(Clone, Copy)]
+struct CompatibilityOptions {
+    min_match_percentage: f64,
+    quarantine: bool, // Skip the sample and carry on, rather than refusing to normalise the cohort
+}
 
-    // Read the field count
-    let fcount = file.read_u32::<LittleEndian>()?;
+impl CompatibilityOptions {
 
-    let mut field_val = HashMap::new();
-    for _ in 0..fcount {
-        let fcode = file.read_u16::<LittleEndian>()?;
-        let offset = file.read_u64::<LittleEndian>()?;
-        field_val.insert(fcode, offset);
+    fn from_config(config: &NormalisationConfig) -> CompatibilityOptions {
+        CompatibilityOptions {
+            min_match_percentage: config.min_match_percentage,
+            quarantine: config.quarantine,
+        }
     }
-
-    // Seek to the position of FID_N_SNPS_READ
-    let num_markers_offset = field_val[&FID_N_SNPS_READ];
-    file.seek(io::SeekFrom::Start(num_markers_offset))?;
-
-    // Read the number of markers
-    let num_markers = file.read_u32::<LittleEndian>()?;
-
-    // Seek to the position of FID_BARCODE
-    let barcode_offset = field_val[&FID_BARCODE];
-    file.seek(io::SeekFrom::Start(barcode_offset))?;
-
-    // Read the barcode
-    let _bcode = file.read_u32::<LittleEndian>()?;
-
-    // Seek to the position of FID_ILLUMINAID
-    let illumina_id_offset = field_val[&FID_ILLUMINAID];
-    file.seek(io::SeekFrom::Start(illumina_id_offset))?;
-
-    // Read Illumina IDs as u32 array
-    let iids = read_u32_array(&mut file, num_markers as usize)?;
-
-    // Seek to the position of FID_MEAN
-    let mean_offset = field_val[&FID_MEAN];
-    file.seek(io::SeekFrom::Start(mean_offset))?;
-
-    // Read means as u16 array
-    let vals = read_u16_array(&mut file, num_markers as usize)?;
-    // let grn_vals = read_u16_array(&mut file, num_markers as usize)?;
-    let f64_vals: Vec<f64> = vals.iter().map(|&u16_val| u16_val as f64).collect();
-
-    Ok((iids, f64_vals))
 }
 
-// Get the directory paths to the idat files to process
-fn process_sample_sheet_line(
-    line: &str,
-    idat_directory: &str,
+// Checks an individual against the manifest and finds where the beads of the layout SNPs sit in its IDAT
+// Returns None when the sample is quarantined, and an error when it must not be normalised at all
+fn sample_bead_positions(
+    sample: &SampleData,
+    layout: &ProbeLayout,
+    options: &CompatibilityOptions,
     rank: i32,
-    _size: i32,
-    ids: &Arc<Mutex<Vec<u32>>>,
-    read_ids: &bool,
-    batch_comment_index: &Option<usize>,
-    array_info_s_index: &Option<usize>,
-    sentrix_id_index: &Option<usize>,
-) -> Vec<(f64, f64)>{
-    let mut data: Vec<(f64, f64)> = Vec::new();
-    // Split the line into fields
-    let record: Vec<&str> = line.split(',').collect();
-    if let Some(batch_comment_index) = batch_comment_index {
-        if let Some(array_info_s_index) = array_info_s_index {
-            if let Some(sentrix_id_index) = sentrix_id_index {
-                // Check if the record has enough fields
-                if record.len() >= 20 {
-                    let batch_comment = record[*batch_comment_index];
-                    let array_info_s = record[*array_info_s_index];
-                    let sentrix_id = record[*sentrix_id_index];
-
-                    // Remove spaces and ensure that batch_comment ends with "_iDATS"
-                    let mut batch_comment_cleaned = batch_comment.replace(" ", "").trim().to_string();
-                    batch_comment_cleaned.push_str("_iDATS");
-
-                    // Construct the file paths for Red and Grn IDAT files
-                    let red_idat_path = construct_red_idat_path(idat_directory, &batch_comment_cleaned, array_info_s, sentrix_id);
-                    let grn_idat_path = construct_grn_idat_path(idat_directory, &batch_comment_cleaned, array_info_s, sentrix_id);
-
-                    // Check the existence of Red and Grn IDAT files
-                    if fs::metadata(&red_idat_path).is_ok() && fs::metadata(&grn_idat_path).is_ok() {
-                        //println!("Node {}: Found Red IDAT file: {}", rank, red_idat_path);
-                        //println!("Node {}: Found Grn IDAT file: {}", rank, grn_idat_path);
-
-                        // Read data from Red and Grn IDAT files
-                        match (
-                            read_idat_values(&red_idat_path, "Red"),
-                            read_idat_values(&grn_idat_path, "Green"),
-                        ) {
-                            (Ok((probe_ids, red_data)), Ok((_, grn_data))) => {
-                                // Lock the shared data vector and push the IdatData
-                                let store: Vec<(f64, f64)> = red_data.iter().zip(grn_data.iter()).map(|(&a, &b)| (a, b)).collect();
-                                data = store;
-
-                                if *read_ids {
-                                    let mut store_id = ids.lock().unwrap();
-                                    *store_id = probe_ids;
-                                }
-                            }
-                            _ => {
-                                println!("Error reading IDAT files.");
-                            }
-                        }
-
-                    } else {
-                        println!("Node {}: Red IDAT file not found: {}", rank, red_idat_path);
-                        println!("Node {}: Grn IDAT file not found: {}", rank, grn_idat_path);
-                    }
-                }
-            }
+    quarantined: &Arc<Mutex<Vec<CompatibilityReport>>>,
+) -> Result<Option<Vec<BeadPosition>>> {
+    let report = CompatibilityReport::check(&sample.sample, &sample.chip_type, &layout.probes, &sample.ids);
+    println!("Node {}: {}", rank, report);
+
+    // A scan that is compatible overall can still miss SNPs the first individual had, and every individual must give the same SNPs
+    let positions = if !report.is_compatible(options.min_match_percentage) {
+        None
+    } else if layout.matches_ids(&sample.ids) {
+        Some(layout.bead_positions.clone())
+    } else {
+        layout.locate_in(&sample.ids)
+    };
+
+    match positions {
+        Some(positions) => Ok(Some(positions)),
+        None if options.quarantine => {
+            println!("Node {}: Quarantined sample {}", rank, report.sample);
+            quarantined.lock().unwrap().push(report);
+            Ok(None)
+        }
+        None => Err(Error::InvalidData {
+            path: None,
+            message: format!(
+                "{:.2}% of {} manifest addresses found, below the {:.2}% minimum or missing SNPs of the probe layout (use --quarantine to skip such samples)",
+                report.match_percentage(), report.manifest_addresses, options.min_match_percentage
+            ),
         }
+        .for_sample(&report.sample)),
     }
-    data
-}
-
-// Function construct the directory path for the red intensity idat for an individial
-fn construct_red_idat_path(
-    idat_directory: &str,
-    batch_comment_cleaned: &str,
-    array_info_s: &str,
-    sentrix_id: &str,
-) -> String {
-    format!(
-        "{}/{}/{}/{}_{}_Red.idat",
-        idat_directory, batch_comment_cleaned, array_info_s, array_info_s, sentrix_id
-    )
 }
 
-// Function construct the directory path for the green intensity idat for an individial
-fn construct_grn_idat_path(
-    idat_directory: &str,
-    batch_comment_cleaned: &str,
-    array_info_s: &str,
-    sentrix_id: &str,
-) -> String {
-    format!(
-        "{}/{}/{}/{}_{}_Grn.idat",
-        idat_directory, batch_comment_cleaned, array_info_s, array_info_s, sentrix_id
-    )
-}
-
-fn read_manifest_file(manifest_directory: &str, addresses: &mut Vec<u32>, bead_set_id: &mut Vec<i32>, unique_bead_set_ids: &mut  Vec<i32>) -> Result<(), std::io::Error> {
-    let manifest_file = std::fs::File::open(manifest_directory)?;
-    let mut is_first_line = true;
-    let manifest_lines = std::io::BufReader::new(manifest_file).lines().skip(7);
-    let mut address_a_id_index = None;
-    let mut address_b_id_index = None;
-    let mut bead_set_id_index = None;
-    let mut number_of_fields = None;
-
-    for manifest_line in manifest_lines {
-        let manifest_line = manifest_line?;
-        let manifest_fields: Vec<&str> = manifest_line.split(',').collect();
-
-        if is_first_line {
-            // Iterate over the manifest fields to find indexes
-            for (index, field) in manifest_fields.iter().enumerate() {
-                match *field {
-                    "AddressA_ID" => {
-                        address_a_id_index = Some(index);
-                    }
-                    "AddressB_ID" => {
-                        address_b_id_index = Some(index);
-                    }
-                    "BeadSetID" => {
-                        bead_set_id_index = Some(index);
-                    }
-                    _ => {}
+// The individuals to process and the template giving the paths of their IDATs
+// Without a sample sheet the individuals are the Red/Grn IDAT pairs found under the IDAT directory
+// The template is checked, and every path built, before any IDAT is read so that mistakes show up at startup
+pub(crate) fn load_samples(config: &RunConfig, rank: i32) -> Result<(SampleSheet, PathTemplate)> {
+    let idat_directory = config.idat_directory()?;
+
+    // Either an Illumina sample sheet or the flat CSV with "Batch Comment", "Array Info.S" and "Sentrix ID"
+    let (mut sample_sheet, default_template) = match &config.sample_sheet {
+        Some(sample_sheet_file) => {
+            let sample_sheet = SampleSheet::read(sample_sheet_file, config.column_mapping().as_ref())?;
+            let default_template = sample_sheet.default_idat_template();
+            (sample_sheet, default_template)
+        }
+        None => {
+            let discovered = discover_samples(idat_directory)?;
+            if rank == 0 {
+                println!("Found {} samples under {}", discovered.sheet.records.len(), idat_directory);
+                for path in &discovered.unpaired {
+                    println!("Unpaired IDAT file, the other channel is missing: {}", path);
                 }
             }
-            number_of_fields = Some(manifest_fields.len());
-            is_first_line = false; // Set is_first_line to false after processing the first line
-            continue;
+            (discovered.sheet, DISCOVERED_TEMPLATE)
         }
-
-        if let Some(number_of_fields) = number_of_fields {
-            if manifest_fields.len() >= number_of_fields {
-                // Determining the unique individual beadsetIDs for address A
-                if let Some(address_a_id_index) = address_a_id_index {
-                    if let Ok(address) = manifest_fields.get(address_a_id_index).unwrap().parse::<u32>() {
-                        if let Some(bead_set_id_index) = bead_set_id_index {
-                            if let Ok(beadset_id) = manifest_fields.get(bead_set_id_index).unwrap().parse::<i32>() {
-                                addresses.push(address);
-                                bead_set_id.push(beadset_id);
-
-                                if !unique_bead_set_ids.contains(&beadset_id) {
-                                    unique_bead_set_ids.push(beadset_id);
-                                }
-                            }
-                        }
-                    }
-                }
-
-                // Determining the unique individual beadsetIDs for address B if it exists
-                if !manifest_fields[address_b_id_index.unwrap()].is_empty() {
-                    if let Some(address_b_id_index) = address_b_id_index {
-                        if let Ok(address_col6) = manifest_fields.get(address_b_id_index).unwrap().parse::<u32>() {
-                            if let Some(bead_set_id_index) = bead_set_id_index {
-                                if let Ok(beadset_id) = manifest_fields.get(bead_set_id_index).unwrap().parse::<i32>() {
-                                    addresses.push(address_col6);
-                                    bead_set_id.push(beadset_id);
-
-                                    if !unique_bead_set_ids.contains(&beadset_id) {
-                                        unique_bead_set_ids.push(beadset_id);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+    };
+
+    // Every individual is processed unless a subset is selected
+    let selection = config.selection()?;
+    if !selection.is_empty() {
+        let sample_count = sample_sheet.records.len();
+        if rank == 0 {
+            for id in selection.unknown_ids(&sample_sheet.records) {
+                println!("Selected sample {} is not in the sample sheet", id);
             }
         }
+        sample_sheet.records = selection.apply(sample_sheet.records);
+        if rank == 0 {
+            println!("Processing {} of {} samples", sample_sheet.records.len(), sample_count);
+        }
     }
 
-    // Create a new vector that combines addresses and bead_set_id
-    let mut combined: Vec<(u32, i32)> = addresses.iter().cloned().zip(bead_set_id.iter().cloned()).collect();
+    let idat_template = PathTemplate::parse(config.idat_template.as_deref().unwrap_or(default_template))?;
+    idat_template.validate(&sample_sheet.columns)?;
+    for record in &sample_sheet.records {
+        idat_template.render(record, Channel::Red)?;
+    }
 
-    // Sort the combined vector by addresses in ascending order
-    combined.sort_by_key(|&(address, _)| address);
+    Ok((sample_sheet, idat_template))
+}
 
-    // Update the addresses and bead_set_id vectors with the sorted values
-    addresses.clear();
-    bead_set_id.clear();
+// Get the directory paths to the idat files of an individual and read them
+// Every error names the individual it was raised for
+pub(crate) fn process_sample_record(
+    record: &SampleRecord,
+    idat_directory: &str,
+    template: &PathTemplate,
+    _rank: i32,
+    _size: i32,
+) -> Result<SampleData> {
+    let sample_id = &record.sample_id;
+
+    // Construct the file paths for Red and Grn IDAT files
+    let red_idat_path = construct_idat_path(idat_directory, template, record, Channel::Red).map_err(|err| err.for_sample(sample_id))?;
+    let grn_idat_path = construct_idat_path(idat_directory, template, record, Channel::Green).map_err(|err| err.for_sample(sample_id))?;
+
+    // Check the existence of Red and Grn IDAT files, either plain or gzip-compressed
+    let red_idat_path = resolve_idat_path(&red_idat_path).ok_or_else(|| Error::MissingIdat { path: red_idat_path }.for_sample(sample_id))?;
+    let grn_idat_path = resolve_idat_path(&grn_idat_path).ok_or_else(|| Error::MissingIdat { path: grn_idat_path }.for_sample(sample_id))?;
+
+    // Read data from Red and Grn IDAT files
+    let red_idat = read_idat_values(&red_idat_path, "Red").map_err(|err| err.for_sample(sample_id))?;
+    let grn_idat = read_idat_values(&grn_idat_path, "Green").map_err(|err| err.for_sample(sample_id))?;
+
+    // The means are zipped by position, so both channels must describe the same probes of the same scan
+    validate_channel_pair(sample_id, &red_idat, &grn_idat)?;
+
+    Ok(SampleData {
+        sample: sample_id.clone(),
+        // The probe ids and chip type are checked against the manifest before the sample is normalised
+        chip_type: red_idat.chip_type().unwrap_or_default(),
+        ids: red_idat.ids().collect(),
+        // Widen the mapped means straight into the (red, green) pairs
+        data: red_idat.means().zip(grn_idat.means()).map(|(a, b)| (a as f64, b as f64)).collect(),
+    })
+}
 
-    for (addr, bead_id) in combined {
-        addresses.push(addr);
-        bead_set_id.push(bead_id);
+// Function construct the path to the idat of one channel of an individial from the path template
+// Relative templates are resolved against the idat directory of the run config
+pub(crate) fn construct_idat_path(
+    idat_directory: &str,
+    template: &PathTemplate,
+    record: &SampleRecord,
+    channel: Channel,
+) -> Result<String> {
+    let path = template.render(record, channel)?;
+    if path.starts_with('/') {
+        Ok(path)
+    } else {
+        Ok(format!("{}/{}", idat_directory, path))
     }
-
-    Ok(())
 }
 
 // Function initialises the vectors in a hash map according to their Beadset and stores the indexes of the probe IDs in the idat file for each beadset
@@ -329,42 +222,6 @@ fn initialize_storage(vector_names: &Arc<Mutex<Vec<i32>>>) ->
 }
 
 
-// Function populate the group vectors for indexes with the indexes they should extract data from in the color intensity files
-fn process_vectors(
-    vectors: &Arc<Mutex<HashMap<i32, Vec<i32>>>>,
-    vector_ids: &Arc<Mutex<HashMap<i32, Vec<u32>>>>,
-    idds: &Arc<Mutex<Vec<u32>>>,
-    address: &Vec<u32>,
-    bead_set_id: &Vec<i32>,
-) {
-    let ids = idds.lock().unwrap();
-    let mut vectors_ind = vectors.lock().unwrap();
-    let mut vectors_ids = vector_ids.lock().unwrap();
-
-    let mut found: usize = 0;
-    for (index, iid) in ids.iter().enumerate() {
-        for (addr_index, addr) in address.iter().enumerate().skip(found) {
-            if iid < addr {
-                break;
-            }
-
-            if iid == addr {
-                //Store the index at which the particular address is found in associated beadset vector
-                if let Some(index_vec) = vectors_ind.get_mut(&bead_set_id[addr_index]) {
-                    index_vec.push(index as i32);
-                }
-
-                // Store the actual address for that index in the associated beadset vector
-                if let Some(index_vector) = vectors_ids.get_mut(&bead_set_id[addr_index]) {
-                    index_vector.push(*iid);
-                }
-                break;
-            }
-            found += 1;
-        }
-    }
-}
-
 // Function split the individual data and store them according to their BeadSetIDs
 fn populate_vectors(
     vectors_grp: &mut HashMap<i32, Vec<(f64, f64)>>,
@@ -424,229 +281,297 @@ fn recontruct_individual_vector(
 }
 
 
-pub fn main_processing(_world: &SystemCommunicator, rank: i32, size: i32, line_count: &mut i32, store_lines: &mut Vec<i32>) -> Result<Vec<Vec<(f64, f64)>>, io::Error> {
+// The probe layout and the BeadSetID groups every individual of a process is split into
+struct BeadsetGroups {
+    layout: Arc<Mutex<ProbeLayout>>,
+    vectors_ind_map: Arc<Mutex<HashMap<i32, Vec<i32>>>>,
+    vector_ids: Arc<Mutex<HashMap<i32, Vec<u32>>>>,
+    vector_names: Arc<Mutex<Vec<i32>>>,
+}
+
+// An individual normalised within its BeadSetIDs, the transforms fitted to them and the groups left as they were
+type NormalisedSample = (Vec<(f64, f64)>, Vec<TransformRecord>, Vec<Error>);
+
+// The transforms of an earlier run, per sample, to normalise with instead of fitting new ones
+pub type StoredTransforms = HashMap<String, Vec<TransformRecord>>;
+
+// The individuals of a process normalised within their BeadSetIDs, in the SNP order of the layout
+pub struct NormalisedIndividuals {
+    pub samples: Vec<String>,
+    pub individuals: Vec<Vec<(f64, f64)>>,
+    pub transforms: TransformTable, // Fitted in this run, or the stored ones that were applied
+    pub layout: ProbeLayout,
+}
+
+// Normalises an individual within its BeadSetIDs, after checking it against the manifest
+// With stored transforms the individual is normalised with its transforms from the earlier run
+// Returns None when the individual is quarantined, and an error naming the individual when it cannot be normalised
+fn normalise_sample(
+    sample: &SampleData,
+    groups: &BeadsetGroups,
+    compatibility: &CompatibilityOptions,
+    rank: i32,
+    quarantined: &Arc<Mutex<Vec<CompatibilityReport>>>,
+    stored: Option<&StoredTransforms>,
+    fit: &NormalizationConfig,
+) -> Result<Option<NormalisedSample>> {
+    // Combine the bead intensities into the X/Y intensities of every SNP (Infinium I and II)
+    let snp_data = {
+        let layout = groups.layout.lock().unwrap();
+        match sample_bead_positions(sample, &layout, compatibility, rank, quarantined)? {
+            Some(positions) => assemble_snp_intensities(&layout.probes, &positions, &sample.data).map_err(|err| err.for_sample(&sample.sample))?,
+            None => return Ok(None),
+        }
+    };
+
+    // Initialise the vector to store the data in beadsetID
+    // Initialising every time because we had a problem when the vector was being shared across the threads which is a problem
+    let mut vectors = initialize_storage(&groups.vector_names);
+
+    // Populate the vectors variable with the data for each beadsetID
+    populate_vectors(&mut vectors, &groups.vectors_ind_map, &snp_data);
 
-    // Code is set to requie atleast 4 nodes
-    if size < 4 {
-        println!("Error: This program requires at least 4 processes.");
+    // Normalise the data intensities across beadSet
+    let (transforms, degenerate) = match stored {
+        Some(stored) => {
+            let transforms = stored.get(&sample.sample).cloned().ok_or_else(|| Error::MissingSample { sample: sample.sample.clone() })?;
+            Normalise::apply_beadset_transforms(&sample.sample, &mut vectors, &groups.vector_names, &transforms)
+                .map(|_| (transforms, Vec::new()))
+        }
+        None => Normalise::within_beadset_normalisation(&sample.sample, &mut vectors, &groups.vector_names, fit),
     }
+    .map_err(|err| err.for_sample(&sample.sample))?;
 
-    // Read command-line arguments
-    let args: Vec<String> = env::args().collect();
+    // Combine the data to make one individual given the data in beadsetIDs for that individual
+    let degenerate = degenerate.into_iter().map(|group| group.for_sample(&sample.sample)).collect();
+    Ok(Some((recontruct_individual_vector(&mut vectors, &groups.vector_ids, &groups.vector_names), transforms, degenerate)))
+}
 
-    // Command-line arguments
-    let sample_sheet_file = &args[1];
-    let idat_directory = &args[2];
-    let manifest_directory = &args[3];
+// Reads and normalises within BeadSetIDs the individuals of this process, every size-th individual of the sample sheet starting at rank
+// The individuals are returned in sample sheet order, leaving out the quarantined ones, with the transforms fitted to them
+// An individual that fails does not stop the others, but once they are all done the failures are listed and the run fails
+pub fn main_processing(
+    rank: i32,
+    size: i32,
+    line_count: &mut i32,
+    config: &RunConfig,
+    stored: Option<&StoredTransforms>,
+) -> Result<NormalisedIndividuals> {
 
-    let mut addresses: Vec<u32> = Vec::new(); // Stores the probe addresses from the manifest file
-    let mut bead_set_id: Vec<i32> = Vec::new(); // Stores the BeadSetID associated with the probe addresses from the manifest file
+    // Settings of the run, from the config file and the command line
+    let idat_directory = config.idat_directory()?;
+    let manifest_directory = config.manifest()?;
 
-    let ids: Arc<Mutex<Vec<u32>>> = Arc::new(Mutex::new(Vec::new())); // Stores the probe ids from the idat files
-    // Each node only need to store when processing the first individual
+    // Samples whose IDAT does not match the manifest either stop the run or are quarantined
+    let compatibility = CompatibilityOptions::from_config(&config.normalisation);
+    let quarantined: Arc<Mutex<Vec<CompatibilityReport>>> = Arc::new(Mutex::new(Vec::new()));
 
-    let shared_idat_directory = Arc::new(idat_directory.to_string());
-    let shared_sample_sheet_file = Arc::new(sample_sheet_file.to_string());
-    let mut is_first_line = true; // Flag to check if it's the first line
-    let shared_sample_sheet_file = Arc::clone(&shared_sample_sheet_file);
-    let mut shared_bool = true; // Flag variable used to determine if the nodes are processing the first individual or not
     let mut vector_names: Vec<i32> = Vec::new(); // Stores the different beadsetIDs without repeatition
 
-    // Opted for each node to read the manifest file on its own, rather than having the root node reading and sharing.
-    // All the other nodes must wait for the root node to process and also increase computation time since the master node must now broadcast its results
-    let _ = read_manifest_file(&manifest_directory, &mut addresses, &mut bead_set_id, &mut vector_names);
-    
+    // The SNPs of the manifest, where their beads sit in the IDAT arrays and their BeadSetID groups
+    // Built from the first individual, or loaded from the cache written next to the manifest by an earlier run or another node
+    let layout: Arc<Mutex<ProbeLayout>> = Arc::new(Mutex::new(ProbeLayout::default()));
+
     // Vectors_ind_map stores the indexes of the probe addresses each beadsetID group will be extracting from each individual
     // Vector_ids stores the actual addresses from at that specific index for each beadSetID. These ids are used to reconstruct the data of the individual from beadsetID groups
     let (vectors_ind_map, vector_ids) = initialize_vectors(&mut vector_names);
 
-    // Variables from the sample sheet used to construct the directory for each individual
-    let mut batch_comment  = None;
-    let mut array_info_s = None;
-    let mut sentrix_id = None;
-
     // Sharing across threads
-    let vector_names: Arc<Mutex<Vec<i32>>> = Arc::new(Mutex::new(vector_names)); 
-    let mut number_of_lines_per_node = 0;
-
-    // Stores the data for the individuals processed by a node
-    let all_individuals: Arc<Mutex<Vec<Vec<(f64, f64)>>>> = Arc::new(Mutex::new(Vec::new())); 
-
-    if let Ok(file) = File::open(&*shared_sample_sheet_file) {
-        let reader = std::io::BufReader::new(file);
-        // Open and read the sample sheet file
-        println!("Node {}: Processing the Sample Sheet...", rank);
-        // Create a vector to store thread handles with explicit type annotation
-        let mut handles: Vec<JoinHandle<()>> = Vec::new();
-        let mut num = 1;
-        for line in reader.lines() {
-            let line = line.unwrap();
-
-            // Use the first line to determine the columns to be used to construct the directory to individuals data
-            if is_first_line {
-                is_first_line = false; // Set the flag to false after processing the first line
-                let file_columns: Vec<&str> = line.split(',').collect();
-
-                for (index, field) in file_columns.iter().enumerate() {
-                    match *field {
-                        "Batch Comment" => {
-                            batch_comment = Some(index);
-                        }
-                        "Array Info.S" => {
-                            array_info_s = Some(index);
-                        }
-                        "Sentrix ID" => {
-                            sentrix_id = Some(index);
-                        }
-                        _ => {}
-                    }
-                }
+    let groups = BeadsetGroups {
+        layout,
+        vectors_ind_map,
+        vector_ids,
+        vector_names: Arc::new(Mutex::new(vector_names)),
+    };
+
+    // The individuals to process and where their IDATs are
+    let (sample_sheet, idat_template) = load_samples(config, rank)?;
+
+    // Each process handles a set number of individuals
+    // Some processes might handle at most one more individual than the others since the individuals might not be evenly distributive across the processes
+    let mut records = Vec::new();
+    for record in sample_sheet.records {
+        if *line_count % size == rank {
+            records.push(record);
+        }
+        *line_count += 1;
+    }
 
-                continue; // Skip the first line (it is the row with the column names)
+    println!("Node {}: Processing the Sample Sheet...", rank);
+    let mut samples: Vec<String> = Vec::new();
+    let mut all_individuals: Vec<Vec<(f64, f64)>> = Vec::new();
+    let mut transforms = TransformTable::default();
+    let mut failures: Vec<Error> = Vec::new();
+    let mut degenerate: Vec<Error> = Vec::new(); // BeadSetID groups too small to normalise, left as they were
+    let mut records = records.into_iter();
+    let mut layout_built = true;
+
+    // Use the first individual to set up the probe layout, there is no need to perform this operation more than once
+    // An individual whose IDATs could not be read leaves the probe layout to the next one
+    for record in records.by_ref() {
+        let sample = match process_sample_record(&record, idat_directory, &idat_template, rank, size) {
+            Ok(sample) => sample,
+            Err(err) => {
+                failures.push(err);
+                continue;
             }
+        };
+
+        {
+            let mut layout = groups.layout.lock().unwrap();
+            *layout = match ProbeLayout::load_or_build(manifest_directory, &sample.ids, &sample.chip_type) {
+                Ok(layout) => layout,
+                Err(err) => {
+                    // Every individual needs the layout, so none of the rest can be normalised without it
+                    failures.push(err);
+                    layout_built = false;
+                    break;
+                }
+            };
 
-            if *line_count > 20 {
-                break;
-            }
+            *groups.vector_names.lock().unwrap() = layout.unique_bead_set_ids.clone();
+            groups.vectors_ind_map.lock().unwrap().extend(layout.beadset_indexes.iter().cloned());
+            groups.vector_ids.lock().unwrap().extend(layout.beadset_ids.iter().cloned());
+        }
 
-            // Each mpi process will process a set number of inviduals 
-            // Some mpi processes might process at most one more individual than the other processes since the individuals might not be evenly distributive across the mpi processes
-            if *line_count % size == rank {
-                // Initialising the variables for sharing across threads
-                let vector_names = Arc::clone(&vector_names);
-                let shared_idat_directory = Arc::clone(&shared_idat_directory);
-                let ids = Arc::clone(&ids);
-                let all_individuals = Arc::clone(&all_individuals);
-                let vectors_ind_map = Arc::clone(&vectors_ind_map);
-                let vector_ids = Arc::clone(&vector_ids);
-
-                // Use the first individual in the to process the vectors_ind_map, vectors_ids, and ids
-                // There is no need to perform this operation more than once
-                if shared_bool {
-                    // Initialise the vector to store the data in beadsetID
-                    // Initialising every time because we had a problem when the vector was being shared across the threads which is a problem
-                    let mut vectors = initialize_storage(&vector_names);
-                    let shared_data = process_sample_sheet_line(&line, &shared_idat_directory, rank, size, &ids, &shared_bool, &batch_comment, &array_info_s, &sentrix_id);
-                    shared_bool = false;
-
-                    // Not necessary but just redundancy in ensuring that the process_vectors is only called once 
-                    if num == 1 {
-                        process_vectors(&vectors_ind_map, &vector_ids, &ids, &addresses, &bead_set_id);
-                    }
-
-                    // Populate the vectors variable with the data for each beadsetID
-                    populate_vectors(&mut vectors, &vectors_ind_map, &shared_data);
-                    num = 2;
-
-                    // Normalise the data intensities across beadSet
-                    let _ = Normalise::within_beadset_normalisation(&mut vectors, &vector_names);
-
-                    // Combine the data to make one individual given the data in beadsetIDs for that individual
-                    let ind_vec = recontruct_individual_vector(&mut vectors, &vector_ids, &vector_names);
-
-                    // Store the processed individual
-                    let mut all_individuals =  all_individuals.lock().unwrap();
-                    all_individuals.push(ind_vec.clone());
-                    println!("Print Done");
-                }else{
-
-                    let handle = thread::spawn(move || {
-                        let mut vectors = initialize_storage(&vector_names);
-                        let shared_data = process_sample_sheet_line(&line, &shared_idat_directory, rank, size, &ids, &false, &batch_comment, &array_info_s, &sentrix_id);
-                        println!("Print {}", shared_data.len());
-                        populate_vectors(&mut vectors, &vectors_ind_map, &shared_data);
-                        let _ = Normalise::within_beadset_normalisation(&mut vectors, &vector_names);
-                        let ind_vec = recontruct_individual_vector(&mut vectors, &vector_ids, &vector_names);
-                        let mut all_individuals =  all_individuals.lock().unwrap();
-                        all_individuals.push(ind_vec.clone());
-                    });
-                    handles.push(handle);
-                    
-                }
-                // Records the number of individuals the node processed
-                number_of_lines_per_node = number_of_lines_per_node + 1;
+        match normalise_sample(&sample, &groups, &compatibility, rank, &quarantined, stored, &config.normalisation.fit) {
+            Ok(Some((individual, records, groups))) => {
+                samples.push(sample.sample);
+                all_individuals.push(individual);
+                transforms.records.extend(records);
+                degenerate.extend(groups);
             }
-            
-            *line_count += 1;
-        }
-        
-        // Wait for all threads to finish
-        for handle in handles {
-            handle.join().unwrap();
+            Ok(None) => {}
+            Err(err) => failures.push(err),
         }
-        
+        break;
     }
 
-    _world.barrier();
-    println!("Node {}: Sample Sheet successfully processed...", rank);
+    // The remaining individuals are normalised on the thread pool
+    let records: Vec<SampleRecord> = if layout_built { records.collect() } else { Vec::new() };
+    let normalised = records
+        .par_iter()
+        .map(|record| {
+            let sample = process_sample_record(record, idat_directory, &idat_template, rank, size)?;
+            let normalised = normalise_sample(&sample, &groups, &compatibility, rank, &quarantined, stored, &config.normalisation.fit)?;
+            Ok(normalised.map(|normalised| (sample.sample, normalised)))
+        })
+        .collect::<Vec<_>>();
+    for individual in normalised {
+        match individual {
+            Ok(Some((sample, (individual, records, groups)))) => {
+                samples.push(sample);
+                all_individuals.push(individual);
+                transforms.records.extend(records);
+                degenerate.extend(groups);
+            }
+            Ok(None) => {}
+            Err(err) => failures.push(err),
+        }
+    }
 
-    // Node send to the master node, the number of individuals that it processed
-    // Vital for the master node to have this information so that it knows how many individuals it will recieve from the particular node ahead of time
-    if rank != 0 {
-        // Send a single variable from process with rank != 0 to process 0
-        _world.process_at_rank(0).send(&number_of_lines_per_node);
+    let quarantined = quarantined.lock().unwrap();
+    if !quarantined.is_empty() {
+        let names: Vec<&str> = quarantined.iter().map(|report| report.sample.as_str()).collect();
+        println!("Node {}: {} samples quarantined: {}", rank, quarantined.len(), names.join(", "));
     }
 
-    if rank == 0 {
-        // Receive the single variable sent by other processes
-        for i in 1..size {
-            let (msg, _status) = _world.process_at_rank(i).receive::<i32>();
-            store_lines.push(msg);
+    if !degenerate.is_empty() {
+        println!("Node {}: {} BeadSetID groups were left unnormalised:", rank, degenerate.len());
+        for group in &degenerate {
+            println!("  {}", group);
         }
     }
 
-    store_lines.push(number_of_lines_per_node);
-    _world.barrier();
-    let result = Ok(all_individuals.lock().unwrap().clone());
-
-    //Return Hashmaps of vectors
-    result
-}
-
-//Function for normalisation within SNP across all the individuals
-pub fn snp_normalisation(individuals: &Vec<Vec<(f64,f64)>>) {
-    println!("Normalising Across SNPs...");
-
-    for single_individual in individuals {
-        let people: Arc<Mutex<Vec<(f64,f64)>>> = Arc::new(Mutex::new(single_individual.to_vec()));
-        let _ = Normalise::within_snp_normalisation(&people);
+    if !failures.is_empty() {
+        eprintln!("Node {}: {} samples failed:", rank, failures.len());
+        for failure in &failures {
+            eprintln!("  {}", failure);
+        }
+        eprintln!("Node {}: Fix these samples or leave them out with --exclude", rank);
+        return Err(Error::InvalidInput(format!("{} of the samples could not be normalised", failures.len())));
     }
 
-    println!("Normalisation Across SNPs complete...");
+    let layout = groups.layout.lock().unwrap().clone();
+    transforms.manifest_hash = layout.manifest_hash;
+    Ok(NormalisedIndividuals { samples, individuals: all_individuals, transforms, layout })
 }
 
-// pub fn snp_normalisation(individuals: &Vec<Vec<(f64,f64)>>) {
-//     println!("Normalising Across SNPs... {}", individuals.len());
-
-//     individuals.par_iter().for_each(|single_individual| {
-//         let people = Arc::new(Mutex::new(single_individual.to_vec()));
-//         let _ = Normalise::within_snp_normalisation(&people);
-//     });
+// Writes the transforms of a run as <name>.json and <name>.tsv, in the output directory or else the working directory
+pub fn write_transforms(transforms: &TransformTable, config: &RunConfig, name: &str) -> Result<()> {
+    let output_directory = config.output_directory.as_deref().unwrap_or(".");
+    fs::create_dir_all(output_directory).map_err(|err| Error::from(err).in_file(output_directory))?;
 
-//     println!("Normalisation Across SNPs complete...");
-// }
-
-// Function to transpose the matrix of individuals data
-pub fn transpose_matrix_in_place(matrix: &mut Vec<Vec<f64>>) {
+    let path = Path::new(output_directory).join(name);
+    let (json_path, tsv_path) = (path.with_extension("json"), path.with_extension("tsv"));
+    transforms.write_json(&json_path.to_string_lossy())?;
+    transforms.write_tsv(&tsv_path.to_string_lossy())?;
+    println!("Wrote {} BeadSetID transforms to {} and {}", transforms.records.len(), json_path.display(), tsv_path.display());
+    Ok(())
+}
 
-    println!("Transposition of All individuals Matrix...");
-    // Check if the matrix is empty
-    if matrix.is_empty() {
-        return;
+// Writes the normalised X/Y intensities of an individual, one layout SNP per line
+fn write_individual(path: &Path, individual: &[(f64, f64)], layout: &ProbeLayout) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "SNP\tX\tY")?;
+    for (&(probe_index, _, _), &(x, y)) in layout.bead_positions.iter().zip(individual) {
+        writeln!(writer, "{}\t{}\t{}", layout.probes[probe_index].name, x, y)?;
     }
+    writer.flush()
+}
 
-    let rows = matrix.len();
-    let cols = matrix[0].len();
+// Writes every individual to <sample>.tsv, in the output directory or else the working directory
+pub fn write_individuals(samples: &[String], individuals: &[Vec<(f64, f64)>], layout: &ProbeLayout, config: &RunConfig) -> Result<()> {
+    let output_directory = config.output_directory.as_deref().unwrap_or(".");
+    fs::create_dir_all(output_directory).map_err(|err| Error::from(err).in_file(output_directory))?;
 
-    let mut transposed = vec![vec![0.0; rows]; cols]; // Create a new matrix with transposed dimensions
+    for (sample, individual) in samples.iter().zip(individuals) {
+        let path = Path::new(output_directory).join(format!("{}.tsv", sample));
+        write_individual(&path, individual, layout).map_err(|err| Error::from(err).in_file(&path.to_string_lossy()))?;
+    }
+    println!("Wrote the intensities of {} individuals to {}", individuals.len(), output_directory);
+    Ok(())
+}
 
-    for i in 0..rows {
-        for j in 0..cols {
-            transposed[j][i] = matrix[i][j]; // Transpose elements
+//Function for normalisation within SNP across all the individuals
+// Every entry holds one SNP of all the individuals, the normalised SNPs are returned in the same order
+// Fails after listing every SNP that cannot be normalised, with too few individuals left after removing its outliers
+pub fn snp_normalisation(snps: &[Vec<(f64, f64)>], fit: &NormalizationConfig) -> Result<Vec<Vec<(f64, f64)>>> {
+    println!("Normalising Across SNPs... {}", snps.len());
+
+    let normalised: Vec<Result<Vec<(f64, f64)>>> = snps
+        .par_iter()
+        .enumerate()
+        .map(|(snp, single_snp)| {
+            let people = Arc::new(Mutex::new(single_snp.to_vec()));
+            Normalise::within_snp_normalisation(&people, fit).map_err(|err| match err {
+                Error::TooFewPoints { points, .. } => Error::DegenerateSnp { snp, points },
+                err => err,
+            })?;
+            let normalised = people.lock().unwrap().clone();
+            Ok(normalised)
+        })
+        .collect();
+
+    let failures: Vec<&Error> = normalised.iter().filter_map(|snp| snp.as_ref().err()).collect();
+    if !failures.is_empty() {
+        eprintln!("{} of {} SNPs failed:", failures.len(), snps.len());
+        for failure in &failures {
+            eprintln!("  {}", failure);
         }
+        return Err(Error::InvalidInput(format!("{} of the SNPs could not be normalised", failures.len())));
     }
 
-    *matrix = transposed; // Update the original matrix with the transposed data
+    println!("Normalisation Across SNPs complete...");
+    normalised.into_iter().collect()
+}
+
+// Turns the SNPs of every individual into every individual of each SNP, the layout the within-SNP stage works on
+pub fn transpose_individuals(individuals: &[Vec<(f64, f64)>]) -> Result<Vec<Vec<(f64, f64)>>> {
+    let snp_count = individuals.first().map_or(0, Vec::len);
+    if individuals.iter().any(|individual| individual.len() != snp_count) {
+        return Err(Error::InvalidData { path: None, message: "The individuals do not all have the same number of SNPs".to_string() });
+    }
 
-    println!("Transposition of All individuals Matrix Completed...");
+    Ok((0..snp_count).map(|snp| individuals.iter().map(|individual| individual[snp]).collect()).collect())
 }
@@ -0,0 +1,84 @@
+use crate::cli::InspectArgs;
+use normalisation::idat::{ArrayStatistics, IdatSummary, MappedIdat};
+use normalisation::Result;
+
+// Reads an IDAT through the same reader used by the pipeline and decodes every field
+fn summarise(path: &str) -> Result<IdatSummary> {
+    let idat = MappedIdat::open(path)?.decode().map_err(|err| err.in_file(path))?;
+    Ok(IdatSummary::from_idat(path, &idat))
+}
+
+fn print_statistics(name: &str, statistics: &ArrayStatistics) {
+    if statistics.count == 0 {
+        println!("  {:<10} not present", name);
+        return;
+    }
+
+    println!(
+        "  {:<10} n={} min={} max={} mean={:.2} median={:.1} sd={:.2}",
+        name, statistics.count, statistics.min, statistics.max, statistics.mean, statistics.median, statistics.std_dev
+    );
+}
+
+fn print_summary(summary: &IdatSummary) {
+    let text = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+
+    println!("{}", summary.path);
+    println!("  Version:          {}", summary.version);
+    println!("  Barcode:          {}", text(&summary.barcode));
+    println!("  Chip type:        {}", text(&summary.chip_type));
+    println!("  Sentrix position: {}", text(&summary.sentrix_position));
+    println!("  Manifest:         {}", text(&summary.manifest));
+    println!("  Red/Green flag:   {}", summary.red_green.map_or("-".to_string(), |flag| flag.to_string()));
+    println!("  Markers:          {}", summary.num_markers);
+
+    println!("  Field table:");
+    for (code, offset) in &summary.field_offsets {
+        println!("    {:>5} @ {}", code, offset);
+    }
+
+    println!("  Statistics:");
+    print_statistics("Mean", &summary.means);
+    print_statistics("SD", &summary.std_devs);
+    print_statistics("NBeads", &summary.n_beads);
+    println!("  {:<10} {}", "No beads", summary.zero_bead_probes);
+
+    println!("  Run info:");
+    for run in &summary.run_info {
+        println!(
+            "    {} | {} | {} | {} | {}",
+            run.run_time, run.block_type, run.block_pars, run.block_code, run.code_version
+        );
+    }
+}
+
+// Entry point of the inspect subcommand, returns the process exit code
+pub fn run(args: &InspectArgs) -> i32 {
+    let mut summaries = Vec::new();
+    let mut failed = 0;
+    for path in &args.idats {
+        match summarise(path) {
+            Ok(summary) => summaries.push(summary),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                failed += 1;
+            }
+        }
+    }
+
+    if args.json {
+        match serde_json::to_string_pretty(&summaries) {
+            Ok(output) => println!("{}", output),
+            Err(err) => {
+                eprintln!("Error serialising summaries: {}", err);
+                return 1;
+            }
+        }
+    } else {
+        for summary in &summaries {
+            print_summary(summary);
+        }
+    }
+
+    if failed > 0 { 1 } else { 0 }
+}
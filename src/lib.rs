@@ -4,6 +4,12 @@ pub mod stage3;
 pub mod stage4;
 pub mod stage5;
 pub mod apply_normalisation;
+pub mod error;
+pub mod idat;
+pub mod manifest;
+pub mod sample_sheet;
+
+pub use error::{Error, Result};
 
 
 #[global_allocator]
@@ -0,0 +1,48 @@
+use crate::config::RunConfig;
+use crate::idat_processing::{self, NormalisedIndividuals};
+use normalisation::{Error, Result};
+use std::env;
+
+// Set by mpirun to the number of processes it started (Open MPI, then MPICH and Intel MPI)
+const MPI_SIZE_VARIABLES: [&str; 2] = ["OMPI_COMM_WORLD_SIZE", "PMI_SIZE"];
+
+// Without the mpi feature every process started by mpirun would normalise the whole cohort on its own
+fn check_not_under_mpirun() -> Result<()> {
+    for variable in MPI_SIZE_VARIABLES {
+        let size = env::var(variable).ok().and_then(|size| size.parse::<usize>().ok());
+        if let Some(size) = size.filter(|&size| size > 1) {
+            return Err(Error::InvalidInput(format!(
+                "Started as one of {} MPI processes, but this build runs in a single process; rebuild with --features mpi",
+                size
+            )));
+        }
+    }
+    Ok(())
+}
+
+// Runs the whole pipeline in this process, the samples and the SNPs are spread over the threads of the rayon pool
+// The individuals come back normalised within BeadSetIDs and within SNPs, with the transforms of the BeadSetID stage
+pub fn normalise(config: &RunConfig) -> Result<NormalisedIndividuals> {
+    check_not_under_mpirun()?;
+
+    let mut number_of_individuals: i32 = 0;
+    let mut normalised = idat_processing::main_processing(0, 1, &mut number_of_individuals, config, None)?;
+    println!("Normalised {} of {} individuals within BeadSetIDs", normalised.individuals.len(), number_of_individuals);
+
+    // The within-SNP stage takes every individual of one SNP at a time
+    let snps = idat_processing::transpose_individuals(&normalised.individuals)?;
+    let snps = idat_processing::snp_normalisation(&snps, &config.normalisation.fit)?;
+    println!("Normalised {} SNPs across {} individuals", snps.len(), normalised.individuals.len());
+
+    // Turned back into every SNP of each individual
+    normalised.individuals = idat_processing::transpose_individuals(&snps)?;
+    Ok(normalised)
+}
+
+// Writes the transforms and the intensities of every individual
+pub fn run(config: &RunConfig) -> Result<()> {
+    let mut normalised = normalise(config)?;
+    normalised.transforms.sort();
+    idat_processing::write_transforms(&normalised.transforms, config, "transforms")?;
+    idat_processing::write_individuals(&normalised.samples, &normalised.individuals, &normalised.layout, config)
+}
@@ -0,0 +1,106 @@
+use crate::error::{invalid_data, Result};
+use std::collections::HashMap;
+
+// How the two alleles of a SNP are read from the beads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AssayType {
+    // One bead type, allele A in the red channel and allele B in the green channel
+    #[default]
+    InfiniumII,
+    // Two bead types (A and B) read in the same channel
+    InfiniumIRed,
+    InfiniumIGreen,
+}
+
+impl AssayType {
+
+    // The BPM stores 0 for Infinium II, 1 for Infinium I in red and 2 for Infinium I in green
+    pub fn from_bpm(assay_type: u8) -> Option<AssayType> {
+        match assay_type {
+            0 => Some(AssayType::InfiniumII),
+            1 => Some(AssayType::InfiniumIRed),
+            2 => Some(AssayType::InfiniumIGreen),
+            _ => None,
+        }
+    }
+
+    // The Color_Channel column of CSV manifests that have one, "Red" or "Grn"
+    pub fn from_color_channel(channel: &str) -> Option<AssayType> {
+        match channel.trim().to_ascii_lowercase().as_str() {
+            "red" => Some(AssayType::InfiniumIRed),
+            "grn" | "green" => Some(AssayType::InfiniumIGreen),
+            _ => None,
+        }
+    }
+
+    // Infinium I probes are read in the colour of the base the single-base extension adds after the probe:
+    // A and T are labelled red, C and G green
+    pub fn from_extension_base(base: char) -> Option<AssayType> {
+        match base.to_ascii_uppercase() {
+            'A' | 'T' => Some(AssayType::InfiniumIRed),
+            'C' | 'G' => Some(AssayType::InfiniumIGreen),
+            _ => None,
+        }
+    }
+}
+
+// The beads a SNP is read from, and the BeadSetID it is normalised with
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnpProbe {
+    pub name: String,
+    pub address_a: u32,
+    pub address_b: Option<u32>,
+    pub assay_type: AssayType,
+    pub bead_set_id: i32,
+}
+
+impl SnpProbe {
+
+    // Builds the (X, Y) intensities of the SNP from the (red, green) means of its beads
+    pub fn intensities(&self, bead_a: (f64, f64), bead_b: Option<(f64, f64)>) -> Option<(f64, f64)> {
+        match self.assay_type {
+            AssayType::InfiniumII => Some(bead_a),
+            AssayType::InfiniumIRed => bead_b.map(|bead_b| (bead_a.0, bead_b.0)),
+            AssayType::InfiniumIGreen => bead_b.map(|bead_b| (bead_a.1, bead_b.1)),
+        }
+    }
+}
+
+// Where the beads of a SNP sit in the IDAT arrays: (probe index, bead A index, bead B index)
+pub type BeadPosition = (usize, usize, Option<usize>);
+
+// Finds the IDAT positions of the beads of every SNP, SNPs with a bead missing from the scan are left out
+pub fn locate_beads(probes: &[SnpProbe], idat_ids: &[u32]) -> Vec<BeadPosition> {
+    let positions: HashMap<u32, usize> = idat_ids.iter().enumerate().map(|(index, &id)| (id, index)).collect();
+
+    probes
+        .iter()
+        .enumerate()
+        .filter_map(|(probe_index, probe)| {
+            let bead_a = *positions.get(&probe.address_a)?;
+            let bead_b = match probe.address_b {
+                Some(address_b) => Some(*positions.get(&address_b)?),
+                None => None,
+            };
+            Some((probe_index, bead_a, bead_b))
+        })
+        .collect()
+}
+
+// Assembles the per-SNP (X, Y) intensities of an individual from its per-bead (red, green) means, one per bead position
+// An Infinium I SNP without a B bead fails the individual, leaving it out would put every later SNP out of line with the positions
+pub fn assemble_snp_intensities(
+    probes: &[SnpProbe],
+    bead_positions: &[BeadPosition],
+    idat_data: &[(f64, f64)],
+) -> Result<Vec<(f64, f64)>> {
+    bead_positions
+        .iter()
+        .map(|&(probe_index, bead_a, bead_b)| {
+            let probe = &probes[probe_index];
+            probe.intensities(idat_data[bead_a], bead_b.map(|index| idat_data[index])).ok_or_else(|| {
+                invalid_data(format!("Infinium I probe {} has no B bead to read its second allele from", probe.name))
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,236 @@
+use super::{sorted_beadset_addresses, AssayType, SnpProbe};
+use crate::error::{invalid_data, Error, Result};
+use crate::idat::{check_remaining, read_idat_string};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+
+const BPM_MAGIC: &[u8; 3] = b"BPM";
+// Newer manifests set this bit on the version number
+const BPM_VERSION_FLAG: u32 = 0x1000;
+
+fn skip_bytes<R: Read>(reader: &mut R, count: u64) -> io::Result<()> {
+    io::copy(&mut reader.by_ref().take(count), &mut io::sink())?;
+    Ok(())
+}
+
+// One locus (SNP) of a binary manifest
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LocusEntry {
+    pub ilmn_id: String,
+    pub name: String,
+    pub ilmn_strand: String,
+    pub snp: String,
+    pub chrom: String,
+    pub ploidy: String,
+    pub species: String,
+    pub map_info: u32,
+    pub top_genomic_seq: String,
+    pub customer_strand: String,
+    pub address_a: u32,
+    pub address_b: u32, // 0 for Infinium II probes, which use a single bead type
+    pub allele_a_probe_seq: String,
+    pub allele_b_probe_seq: String,
+    pub genome_build: String,
+    pub source: String,
+    pub source_version: String,
+    pub source_strand: String,
+    pub source_seq: String,
+    pub exp_clusters: u8,
+    pub intensity_only: u8,
+    pub assay_type: u8, // 0 for Infinium II, 1 and 2 for the Infinium I colours
+    pub ref_strand: Option<String>,
+    pub normalization_id: u8,
+}
+
+impl LocusEntry {
+
+    fn read<R: Read>(reader: &mut R) -> Result<LocusEntry> {
+        let version = reader.read_u32::<LittleEndian>()?;
+        if !(6..=8).contains(&version) {
+            return Err(invalid_data(format!("Unsupported BPM locus entry version {}", version)));
+        }
+
+        let mut entry = LocusEntry {
+            ilmn_id: read_idat_string(reader)?,
+            name: read_idat_string(reader)?,
+            ..Default::default()
+        };
+
+        for _ in 0..3 {
+            read_idat_string(reader)?;
+        }
+        skip_bytes(reader, 4)?; // Locus index
+        read_idat_string(reader)?;
+        entry.ilmn_strand = read_idat_string(reader)?;
+        entry.snp = read_idat_string(reader)?;
+        entry.chrom = read_idat_string(reader)?;
+        entry.ploidy = read_idat_string(reader)?;
+        entry.species = read_idat_string(reader)?;
+
+        let map_info = read_idat_string(reader)?;
+        entry.map_info = map_info.trim().parse::<u32>().map_err(|_| {
+            invalid_data(format!("Locus {} has an invalid MapInfo {:?}", entry.name, map_info))
+        })?;
+
+        entry.top_genomic_seq = read_idat_string(reader)?;
+        entry.customer_strand = read_idat_string(reader)?;
+        entry.address_a = reader.read_u32::<LittleEndian>()?;
+        entry.address_b = reader.read_u32::<LittleEndian>()?;
+        entry.allele_a_probe_seq = read_idat_string(reader)?;
+        entry.allele_b_probe_seq = read_idat_string(reader)?;
+        entry.genome_build = read_idat_string(reader)?;
+        entry.source = read_idat_string(reader)?;
+        entry.source_version = read_idat_string(reader)?;
+        entry.source_strand = read_idat_string(reader)?;
+        entry.source_seq = read_idat_string(reader)?;
+        skip_bytes(reader, 1)?;
+        entry.exp_clusters = reader.read_u8()?;
+        entry.intensity_only = reader.read_u8()?;
+        entry.assay_type = reader.read_u8()?;
+
+        if entry.assay_type > 2 {
+            return Err(invalid_data(format!("Locus {} has an invalid assay type {}", entry.name, entry.assay_type)));
+        }
+        if (entry.address_b == 0) != (entry.assay_type == 0) {
+            return Err(invalid_data(format!(
+                "Locus {} has assay type {} but AddressB {}",
+                entry.name, entry.assay_type, entry.address_b
+            )));
+        }
+
+        // Versions 7 and 8 add the base fractions, version 8 also the reference strand
+        if version >= 7 {
+            skip_bytes(reader, 4 * 4)?;
+        }
+        if version == 8 {
+            entry.ref_strand = Some(read_idat_string(reader)?);
+        }
+
+        Ok(entry)
+    }
+}
+
+// The Illumina binary manifest (.bpm)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BeadPoolManifest {
+    pub version: u32,
+    pub manifest_name: String,
+    pub control_config: String,
+    pub loci: Vec<LocusEntry>, // In the order of the locus names stored in the manifest
+}
+
+impl BeadPoolManifest {
+
+    pub fn read(path: &str) -> Result<BeadPoolManifest> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_reader(&mut BufReader::new(file)).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<BeadPoolManifest> {
+        let mut magic_number = [0u8; 3];
+        reader.read_exact(&mut magic_number)?;
+        if magic_number != BPM_MAGIC[..] {
+            return Err(Error::BadMagic { path: None, format: "BPM", found: magic_number.to_vec() });
+        }
+
+        let format_version = reader.read_u8()?;
+        if format_version != 1 {
+            return Err(Error::UnsupportedVersion { path: None, format: "BPM format", version: format_version as u64 });
+        }
+
+        let mut version = reader.read_u32::<LittleEndian>()?;
+        if version & BPM_VERSION_FLAG == BPM_VERSION_FLAG {
+            version ^= BPM_VERSION_FLAG;
+        }
+        if !(3..=5).contains(&version) {
+            return Err(Error::UnsupportedVersion { path: None, format: "BPM", version: version as u64 });
+        }
+
+        let manifest_name = read_idat_string(reader)?;
+        let control_config = read_idat_string(reader)?;
+        let num_loci = reader.read_u32::<LittleEndian>()? as usize;
+        // Every locus takes at least its index, a name length and a normalization ID, checked before the tables are allocated
+        check_remaining(reader, num_loci, 6, "Locus table")?;
+
+        // The locus index array is not needed, the names give the order instead
+        skip_bytes(reader, 4 * num_loci as u64)?;
+
+        let mut name_lookup: HashMap<String, usize> = HashMap::with_capacity(num_loci);
+        for index in 0..num_loci {
+            name_lookup.insert(read_idat_string(reader)?, index);
+        }
+
+        let mut normalization_ids = vec![0u8; num_loci];
+        reader.read_exact(&mut normalization_ids)?;
+        if let Some(id) = normalization_ids.iter().find(|&&id| id >= 100) {
+            return Err(invalid_data(format!("Manifest contains an invalid normalization ID {}", id)));
+        }
+
+        let mut loci: Vec<Option<LocusEntry>> = vec![None; num_loci];
+        for _ in 0..num_loci {
+            let entry = LocusEntry::read(reader)?;
+            let index = *name_lookup.get(&entry.name).ok_or_else(|| {
+                invalid_data(format!("Locus {} is not in the manifest name list", entry.name))
+            })?;
+            loci[index] = Some(entry);
+        }
+
+        let loci = loci
+            .into_iter()
+            .zip(normalization_ids)
+            .map(|(entry, normalization_id)| {
+                let mut entry = entry.ok_or_else(|| invalid_data("Manifest is missing a locus entry".to_string()))?;
+                // Infinium I and II probes are normalised separately; the wrap mimics GenomeStudio
+                entry.normalization_id = ((normalization_id as u32 + 100 * entry.assay_type as u32) % 256) as u8;
+                Ok(entry)
+            })
+            .collect::<Result<Vec<LocusEntry>>>()?;
+
+        Ok(BeadPoolManifest {
+            version,
+            manifest_name,
+            control_config,
+            loci,
+        })
+    }
+
+    // The beads and assay type of every SNP, sorted by AddressA
+    pub fn snp_probes(&self) -> Vec<SnpProbe> {
+        let mut probes: Vec<SnpProbe> = self
+            .loci
+            .iter()
+            .map(|locus| SnpProbe {
+                name: locus.name.clone(),
+                address_a: locus.address_a,
+                address_b: if locus.address_b == 0 { None } else { Some(locus.address_b) },
+                // The assay type was checked to be 0, 1 or 2 when the locus was read
+                assay_type: AssayType::from_bpm(locus.assay_type).unwrap_or_default(),
+                bead_set_id: locus.normalization_id as i32,
+            })
+            .collect();
+        probes.sort_by_key(|probe| probe.address_a);
+        probes
+    }
+
+    // Fills the address to BeadSetID vectors the pipeline groups probes with, sorted by address
+    // Both bead types of an Infinium I locus are listed under the locus normalization ID
+    pub fn beadset_addresses(&self, addresses: &mut Vec<u32>, bead_set_id: &mut Vec<i32>, unique_bead_set_ids: &mut Vec<i32>) {
+        let mut combined: Vec<(u32, i32)> = Vec::with_capacity(self.loci.len() * 2);
+
+        for locus in &self.loci {
+            let beadset_id = locus.normalization_id as i32;
+            combined.push((locus.address_a, beadset_id));
+            if locus.address_b != 0 {
+                combined.push((locus.address_b, beadset_id));
+            }
+
+            if !unique_bead_set_ids.contains(&beadset_id) {
+                unique_bead_set_ids.push(beadset_id);
+            }
+        }
+
+        sorted_beadset_addresses(combined, addresses, bead_set_id);
+    }
+}
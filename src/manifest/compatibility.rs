@@ -0,0 +1,73 @@
+use super::SnpProbe;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+
+// Samples with fewer of the manifest addresses scanned are not normalised by default
+pub const DEFAULT_MIN_MATCH_PERCENTAGE: f64 = 99.0;
+
+// How well the probes scanned for a sample cover the bead addresses the manifest describes
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CompatibilityReport {
+    pub sample: String,
+    pub chip_type: String,
+    pub manifest_addresses: usize,
+    pub idat_probes: usize,
+    pub matched_addresses: usize,
+    pub missing_addresses: Vec<u32>, // In the manifest but not in the IDAT, sorted
+    pub extra_addresses: Vec<u32>,   // In the IDAT but not in the manifest (control probes among them), sorted
+}
+
+impl CompatibilityReport {
+
+    // Compares the A and B bead addresses of every SNP against the probe IDs of an IDAT
+    pub fn check(sample: &str, chip_type: &str, probes: &[SnpProbe], idat_ids: &[u32]) -> CompatibilityReport {
+        let manifest: HashSet<u32> = probes
+            .iter()
+            .flat_map(|probe| std::iter::once(probe.address_a).chain(probe.address_b))
+            .collect();
+        let scanned: HashSet<u32> = idat_ids.iter().copied().collect();
+
+        let mut missing_addresses: Vec<u32> = manifest.difference(&scanned).copied().collect();
+        let mut extra_addresses: Vec<u32> = scanned.difference(&manifest).copied().collect();
+        missing_addresses.sort_unstable();
+        extra_addresses.sort_unstable();
+
+        CompatibilityReport {
+            sample: sample.to_string(),
+            chip_type: chip_type.to_string(),
+            manifest_addresses: manifest.len(),
+            idat_probes: scanned.len(),
+            matched_addresses: manifest.len() - missing_addresses.len(),
+            missing_addresses,
+            extra_addresses,
+        }
+    }
+
+    pub fn match_percentage(&self) -> f64 {
+        if self.manifest_addresses == 0 {
+            return 0.0;
+        }
+        100.0 * self.matched_addresses as f64 / self.manifest_addresses as f64
+    }
+
+    pub fn is_compatible(&self, min_match_percentage: f64) -> bool {
+        self.match_percentage() >= min_match_percentage
+    }
+}
+
+impl fmt::Display for CompatibilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chip_type = if self.chip_type.is_empty() { "unknown chip" } else { &self.chip_type };
+        write!(
+            f,
+            "Sample {} ({}): {:.2}% of {} manifest addresses found, {} missing, {} extra IDAT probes",
+            self.sample,
+            chip_type,
+            self.match_percentage(),
+            self.manifest_addresses,
+            self.missing_addresses.len(),
+            self.extra_addresses.len()
+        )
+    }
+}
@@ -0,0 +1,343 @@
+use super::{sorted_beadset_addresses, AssayType, SnpProbe};
+use crate::error::{invalid_data, Error, Result};
+use csv::{ReaderBuilder, StringRecord};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+// One row of the [Assay] section
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManifestRecord {
+    pub ilmn_id: String,
+    pub name: String,
+    pub chr: String,
+    pub map_info: Option<u32>,
+    pub snp: String, // Alleles as written in the manifest, e.g. "[A/G]"
+    pub ilmn_strand: String,
+    pub address_a: u32,
+    pub address_b: Option<u32>, // Only Infinium I probes have a second bead type
+    pub bead_set_id: i32,
+    pub assay_type: AssayType,
+}
+
+impl ManifestRecord {
+
+    // Splits the SNP column into its A and B alleles
+    pub fn alleles(&self) -> Option<(String, String)> {
+        let alleles = self.snp.trim().trim_start_matches('[').trim_end_matches(']');
+        let (allele_a, allele_b) = alleles.split_once('/')?;
+        Some((allele_a.to_string(), allele_b.to_string()))
+    }
+}
+
+// One row of the [Controls] section
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControlRecord {
+    pub address: u32,
+    pub control_type: String,
+    pub colour: String,
+    pub name: String,
+}
+
+// An [Assay] row left out of the manifest, with why
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SkippedProbe {
+    pub line: u64,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Heading,
+    Assay,
+    Controls,
+    Other,
+}
+
+// Column positions of the [Assay] section, found from its header row
+struct AssayColumns {
+    ilmn_id: usize,
+    name: usize,
+    chr: Option<usize>,
+    map_info: Option<usize>,
+    snp: Option<usize>,
+    ilmn_strand: Option<usize>,
+    address_a: usize,
+    address_b: Option<usize>,
+    bead_set_id: usize,
+    // Columns the colour channel of Infinium I probes is taken from
+    color_channel: Option<usize>,
+    probe_seq_a: Option<usize>,
+    source_seq: Option<usize>,
+    top_genomic_seq: Option<usize>,
+}
+
+impl AssayColumns {
+
+    fn from_header(header: &StringRecord) -> Result<AssayColumns> {
+        let positions: HashMap<&str, usize> = header.iter().enumerate().map(|(index, name)| (name.trim(), index)).collect();
+        let required = |name: &str| {
+            positions.get(name).copied().ok_or_else(|| Error::ManifestColumnMissing { path: None, column: name.to_string() })
+        };
+
+        Ok(AssayColumns {
+            ilmn_id: required("IlmnID")?,
+            name: required("Name")?,
+            chr: positions.get("Chr").copied(),
+            map_info: positions.get("MapInfo").copied(),
+            snp: positions.get("SNP").copied(),
+            ilmn_strand: positions.get("IlmnStrand").copied(),
+            address_a: required("AddressA_ID")?,
+            address_b: positions.get("AddressB_ID").copied(),
+            bead_set_id: required("BeadSetID")?,
+            color_channel: positions.get("Color_Channel").copied(),
+            probe_seq_a: positions.get("AlleleA_ProbeSeq").copied(),
+            source_seq: positions.get("SourceSeq").copied(),
+            top_genomic_seq: positions.get("TopGenomicSeq").copied(),
+        })
+    }
+
+    // Returns None for Infinium I probes whose colour channel the manifest does not give, after adding them to skipped
+    fn parse(&self, record: &StringRecord, line: u64, skipped: &mut Vec<SkippedProbe>) -> Result<Option<ManifestRecord>> {
+        let text = |index: usize| record.get(index).unwrap_or("").trim().to_string();
+        let optional_text = |index: Option<usize>| index.map(text).unwrap_or_default();
+        let number = |index: usize, column: &str| -> Result<Option<u64>> {
+            let value = text(index);
+            if value.is_empty() {
+                return Ok(None);
+            }
+            value.parse::<u64>().map(Some).map_err(|_| {
+                invalid_data(format!("Manifest line {}: {} value {:?} is not a number", line, column, value))
+            })
+        };
+
+        let address_a = number(self.address_a, "AddressA_ID")?
+            .ok_or_else(|| invalid_data(format!("Manifest line {}: AddressA_ID is empty", line)))?;
+        let address_b = match self.address_b {
+            Some(index) => number(index, "AddressB_ID")?,
+            None => None,
+        };
+        let bead_set_id = number(self.bead_set_id, "BeadSetID")?
+            .ok_or_else(|| invalid_data(format!("Manifest line {}: BeadSetID is empty", line)))?;
+        let map_info = match self.map_info {
+            Some(index) => number(index, "MapInfo")?,
+            None => None,
+        };
+
+        // Only Infinium I probes are read in a single colour channel
+        let assay_type = match address_b {
+            Some(_) => match self.infinium_i_colour(record_text(record)) {
+                Ok(assay_type) => assay_type,
+                Err(reason) => {
+                    skipped.push(SkippedProbe { line, name: text(self.name), reason });
+                    return Ok(None);
+                }
+            },
+            None => AssayType::InfiniumII,
+        };
+
+        let record = ManifestRecord {
+            ilmn_id: text(self.ilmn_id),
+            name: text(self.name),
+            chr: optional_text(self.chr),
+            map_info: map_info.map(|value| value as u32),
+            snp: optional_text(self.snp),
+            ilmn_strand: optional_text(self.ilmn_strand),
+            address_a: address_a as u32,
+            address_b: address_b.map(|value| value as u32),
+            bead_set_id: bead_set_id as i32,
+            assay_type,
+        };
+
+        Ok(Some(record))
+    }
+
+    // The Color_Channel column when the manifest has one, otherwise the base the probe is extended with
+    fn infinium_i_colour(&self, text: impl Fn(Option<usize>) -> String) -> std::result::Result<AssayType, String> {
+        let channel = text(self.color_channel);
+        if !channel.is_empty() {
+            return AssayType::from_color_channel(&channel)
+                .ok_or_else(|| format!("Color_Channel {:?} is neither Red nor Grn", channel));
+        }
+
+        let probe = text(self.probe_seq_a);
+        if probe.is_empty() {
+            return Err("Infinium I probe without a Color_Channel or AlleleA_ProbeSeq".to_string());
+        }
+
+        let sequences = [text(self.source_seq), text(self.top_genomic_seq)];
+        if sequences.iter().all(|sequence| sequence.is_empty()) {
+            return Err("Infinium I probe without a SourceSeq or TopGenomicSeq to place AlleleA_ProbeSeq on".to_string());
+        }
+
+        let base = sequences
+            .iter()
+            .find_map(|sequence| extension_base(&probe, sequence))
+            .ok_or_else(|| format!("AlleleA_ProbeSeq {} does not end next to the variant of its sequence", probe))?;
+        AssayType::from_extension_base(base)
+            .ok_or_else(|| format!("AlleleA_ProbeSeq {} is extended with {:?}, which has no colour", probe, base))
+    }
+}
+
+fn record_text(record: &StringRecord) -> impl Fn(Option<usize>) -> String + '_ {
+    move |index: Option<usize>| index.and_then(|index| record.get(index)).unwrap_or("").trim().to_ascii_uppercase()
+}
+
+fn complement(base: char) -> char {
+    match base {
+        'A' => 'T',
+        'T' => 'A',
+        'C' => 'G',
+        'G' => 'C',
+        other => other,
+    }
+}
+
+// The base the extension adds after an Infinium I probe, found by placing the probe on a sequence written as
+// "...ACG[A/T]TCC..." with the probe's last base on the variant. A probe matching the bases before the variant
+// is extended with the base after it, one matching the other strand with the complement of the base before it.
+// None when the probe matches neither strand, as for most indels.
+fn extension_base(probe: &str, sequence: &str) -> Option<char> {
+    let (before, rest) = sequence.split_once('[')?;
+    let (_, after) = rest.split_once(']')?;
+    let body = probe.get(..probe.len().checked_sub(1)?)?;
+    if body.is_empty() {
+        return None;
+    }
+
+    if before.ends_with(body) {
+        return after.chars().next();
+    }
+    let reverse_after: String = after.chars().rev().map(complement).collect();
+    if reverse_after.ends_with(body) {
+        return before.chars().last().map(complement);
+    }
+    None
+}
+
+// The Illumina CSV manifest with its [Heading], [Assay] and [Controls] sections
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CsvManifest {
+    pub heading: Vec<(String, String)>,
+    pub records: Vec<ManifestRecord>,
+    pub controls: Vec<ControlRecord>,
+    pub skipped: Vec<SkippedProbe>,
+}
+
+impl CsvManifest {
+
+    pub fn read(path: &str) -> Result<CsvManifest> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_reader(BufReader::new(file)).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<CsvManifest> {
+        // Sections have different numbers of columns, so rows are read without a fixed header
+        let mut reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader);
+
+        let mut manifest = CsvManifest::default();
+        let mut section = Section::Other;
+        let mut columns: Option<AssayColumns> = None;
+        let mut seen_assay = false;
+
+        for record in reader.records() {
+            let record = record.map_err(|err| invalid_data(format!("Manifest is not valid CSV: {}", err)))?;
+            let line = record.position().map_or(0, |position| position.line());
+
+            // Rows made of commas only separate the sections
+            if record.iter().all(|field| field.trim().is_empty()) {
+                continue;
+            }
+
+            let first = record.get(0).unwrap_or("").trim();
+            if first.starts_with('[') && first.ends_with(']') {
+                section = match first {
+                    "[Heading]" => Section::Heading,
+                    "[Assay]" => Section::Assay,
+                    "[Controls]" => Section::Controls,
+                    _ => Section::Other,
+                };
+                seen_assay |= section == Section::Assay;
+                continue;
+            }
+
+            match section {
+                Section::Heading => {
+                    let value = record.get(1).unwrap_or("").trim().to_string();
+                    manifest.heading.push((first.to_string(), value));
+                }
+                Section::Assay => match &columns {
+                    Some(columns) => {
+                        if let Some(parsed) = columns.parse(&record, line, &mut manifest.skipped)? {
+                            manifest.records.push(parsed);
+                        }
+                    }
+                    None => columns = Some(AssayColumns::from_header(&record)?),
+                },
+                Section::Controls => {
+                    let address = first.parse::<u32>().map_err(|_| {
+                        invalid_data(format!("Manifest line {}: control address {:?} is not a number", line, first))
+                    })?;
+                    let text = |index: usize| record.get(index).unwrap_or("").trim().to_string();
+                    manifest.controls.push(ControlRecord {
+                        address,
+                        control_type: text(1),
+                        colour: text(2),
+                        name: text(3),
+                    });
+                }
+                Section::Other => {}
+            }
+        }
+
+        if !seen_assay {
+            return Err(invalid_data("Manifest has no [Assay] section".to_string()));
+        }
+        if columns.is_none() {
+            return Err(invalid_data("Manifest [Assay] section has no header row".to_string()));
+        }
+
+        Ok(manifest)
+    }
+
+    // Looks up a value of the [Heading] section, e.g. "Descriptor File Name"
+    pub fn heading_value(&self, key: &str) -> Option<&str> {
+        self.heading.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    // The beads and assay type of every SNP, sorted by AddressA
+    pub fn snp_probes(&self) -> Vec<SnpProbe> {
+        let mut probes: Vec<SnpProbe> = self
+            .records
+            .iter()
+            .map(|record| SnpProbe {
+                name: record.name.clone(),
+                address_a: record.address_a,
+                address_b: record.address_b,
+                assay_type: record.assay_type,
+                bead_set_id: record.bead_set_id,
+            })
+            .collect();
+        probes.sort_by_key(|probe| probe.address_a);
+        probes
+    }
+
+    // Fills the address to BeadSetID vectors the pipeline groups probes with, sorted by address
+    pub fn beadset_addresses(&self, addresses: &mut Vec<u32>, bead_set_id: &mut Vec<i32>, unique_bead_set_ids: &mut Vec<i32>) {
+        let mut combined: Vec<(u32, i32)> = Vec::with_capacity(self.records.len() * 2);
+
+        for record in &self.records {
+            combined.push((record.address_a, record.bead_set_id));
+            if let Some(address_b) = record.address_b {
+                combined.push((address_b, record.bead_set_id));
+            }
+
+            if !unique_bead_set_ids.contains(&record.bead_set_id) {
+                unique_bead_set_ids.push(record.bead_set_id);
+            }
+        }
+
+        sorted_beadset_addresses(combined, addresses, bead_set_id);
+    }
+}
@@ -0,0 +1,197 @@
+use super::SnpProbe;
+use crate::error::{invalid_data, Error, Result};
+use crate::idat::read_idat_string;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+const EGT_VERSION: u32 = 3;
+
+// The normalised (X, Y) intensities of a SNP in the polar coordinates the clusters are stored in: (theta, R)
+pub fn polar_coordinates(x: f64, y: f64) -> (f64, f64) {
+    (2.0 / PI * y.atan2(x), x + y)
+}
+
+// The position and spread of one genotype cluster, in theta and R
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClusterStats {
+    pub theta_mean: f32,
+    pub theta_dev: f32,
+    pub r_mean: f32,
+    pub r_dev: f32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClusterScore {
+    pub cluster_score: f32,
+    pub total_score: f32, // The GenTrain score
+    pub original_score: f32,
+    pub edited: bool,
+}
+
+// The AA, AB and BB clusters of one SNP
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterRecord {
+    pub name: String,
+    pub address: u32,
+    pub aa: ClusterStats,
+    pub ab: ClusterStats,
+    pub bb: ClusterStats,
+    pub intensity_threshold: Option<f32>, // Only stored by data block version 9
+    pub score: ClusterScore,
+}
+
+impl ClusterRecord {
+
+    pub fn gentrain_score(&self) -> f32 {
+        self.score.total_score
+    }
+
+    pub fn cluster_counts(&self) -> (u32, u32, u32) {
+        (self.aa.count, self.ab.count, self.bb.count)
+    }
+
+    // The order of the fields is counts, then R deviations, R means, theta deviations and theta means, each as AA, AB, BB
+    fn read<R: Read>(reader: &mut R, data_block_version: u32) -> Result<ClusterRecord> {
+        let mut counts = [0u32; 3];
+        reader.read_u32_into::<LittleEndian>(&mut counts)?;
+
+        let mut read_triple = || -> Result<[f32; 3]> {
+            let mut values = [0f32; 3];
+            reader.read_f32_into::<LittleEndian>(&mut values)?;
+            Ok(values)
+        };
+        let r_dev = read_triple()?;
+        let r_mean = read_triple()?;
+        let theta_dev = read_triple()?;
+        let theta_mean = read_triple()?;
+
+        let stats = |index: usize| ClusterStats {
+            theta_mean: theta_mean[index],
+            theta_dev: theta_dev[index],
+            r_mean: r_mean[index],
+            r_dev: r_dev[index],
+            count: counts[index],
+        };
+
+        let mut record = ClusterRecord {
+            aa: stats(0),
+            ab: stats(1),
+            bb: stats(2),
+            ..Default::default()
+        };
+
+        if data_block_version >= 9 {
+            record.intensity_threshold = Some(reader.read_f32::<LittleEndian>()?);
+        }
+
+        // Fields GenomeStudio no longer uses
+        let mut unused = [0f32; 14];
+        reader.read_f32_into::<LittleEndian>(&mut unused)?;
+
+        Ok(record)
+    }
+}
+
+// The GenomeStudio cluster file (.egt)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClusterFile {
+    pub gencall_version: String,
+    pub cluster_version: String,
+    pub call_version: String,
+    pub normalization_version: String,
+    pub date_created: String,
+    pub manifest_name: String,
+    pub records: Vec<ClusterRecord>, // In the order stored in the file
+    lookup: HashMap<String, usize>,
+}
+
+impl ClusterFile {
+
+    pub fn read(path: &str) -> Result<ClusterFile> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_reader(&mut BufReader::new(file)).map_err(|err| err.in_file(path))
+    }
+
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<ClusterFile> {
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != EGT_VERSION {
+            return Err(Error::UnsupportedVersion { path: None, format: "Cluster file", version: version as u64 });
+        }
+
+        let mut cluster_file = ClusterFile {
+            gencall_version: read_idat_string(reader)?,
+            cluster_version: read_idat_string(reader)?,
+            call_version: read_idat_string(reader)?,
+            normalization_version: read_idat_string(reader)?,
+            date_created: read_idat_string(reader)?,
+            ..Default::default()
+        };
+
+        if reader.read_u8()? != 1 {
+            return Err(invalid_data("Only WGT cluster files are supported".to_string()));
+        }
+        cluster_file.manifest_name = read_idat_string(reader)?;
+
+        let data_block_version = reader.read_u32::<LittleEndian>()?;
+        if !(8..=9).contains(&data_block_version) {
+            return Err(Error::UnsupportedVersion { path: None, format: "Cluster file data block", version: data_block_version as u64 });
+        }
+        read_idat_string(reader)?; // OPA name
+
+        let num_records = reader.read_u32::<LittleEndian>()? as usize;
+        // Not preallocated, a corrupt count fails at the end of the file instead
+        let mut records = Vec::new();
+        for _ in 0..num_records {
+            records.push(ClusterRecord::read(reader, data_block_version)?);
+        }
+
+        for record in records.iter_mut() {
+            record.score = ClusterScore {
+                cluster_score: reader.read_f32::<LittleEndian>()?,
+                total_score: reader.read_f32::<LittleEndian>()?,
+                original_score: reader.read_f32::<LittleEndian>()?,
+                edited: reader.read_u8()? != 0,
+            };
+        }
+
+        // The genotypes of the samples used for clustering are not needed
+        for _ in 0..num_records {
+            read_idat_string(reader)?;
+        }
+
+        for record in records.iter_mut() {
+            record.name = read_idat_string(reader)?;
+        }
+        for record in records.iter_mut() {
+            record.address = reader.read_u32::<LittleEndian>()?;
+        }
+
+        // The counts are stored twice, both copies have to agree
+        for record in &records {
+            let mut counts = [0u32; 3];
+            reader.read_u32_into::<LittleEndian>(&mut counts)?;
+            if (counts[0], counts[1], counts[2]) != record.cluster_counts() {
+                return Err(invalid_data(format!("Cluster counts of {} do not match its cluster record", record.name)));
+            }
+        }
+
+        cluster_file.lookup = records.iter().enumerate().map(|(index, record)| (record.name.clone(), index)).collect();
+        cluster_file.records = records;
+
+        Ok(cluster_file)
+    }
+
+    // Looks up the clusters of a SNP by the name used in the manifest
+    pub fn get(&self, name: &str) -> Option<&ClusterRecord> {
+        self.lookup.get(name).map(|&index| &self.records[index])
+    }
+
+    // The clusters of every probe, in the order of the probes; SNPs the file does not cluster give None
+    pub fn records_for(&self, probes: &[SnpProbe]) -> Vec<Option<&ClusterRecord>> {
+        probes.iter().map(|probe| self.get(&probe.name)).collect()
+    }
+}
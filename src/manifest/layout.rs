@@ -0,0 +1,282 @@
+use super::{locate_beads, read_snp_probes, snp_beadset_addresses, AssayType, BeadPosition, SnpProbe};
+use crate::error::{invalid_data, Error, Result};
+use crate::idat::{check_remaining, read_idat_string, write_idat_string};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use memmap::Mmap;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, Write};
+
+const LAYOUT_MAGIC: &[u8; 4] = b"PLYT";
+const LAYOUT_VERSION: u32 = 2;
+// Marks an Infinium II SNP, which has no B bead, in the cache file
+const NO_BEAD: u32 = u32::MAX;
+
+// 64-bit FNV-1a, stable across builds so cache keys stay valid
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn hash_file(path: &str) -> Result<u64> {
+    hash_mapped_file(path).map_err(|err| Error::from(err).in_file(path))
+}
+
+fn hash_mapped_file(path: &str) -> io::Result<u64> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(fnv1a(&[]));
+    }
+    // Safety: the manifest is treated as a read-only input for the lifetime of the mapping
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(fnv1a(&mmap))
+}
+
+fn assay_code(assay_type: AssayType) -> u8 {
+    match assay_type {
+        AssayType::InfiniumII => 0,
+        AssayType::InfiniumIRed => 1,
+        AssayType::InfiniumIGreen => 2,
+    }
+}
+
+// Everything the pipeline derives from the manifest and the probe IDs of the first IDAT:
+// the SNPs, where their beads are in the IDAT arrays, and which SNPs each BeadSetID normalises together
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeLayout {
+    pub manifest_hash: u64,
+    pub chip_id: String,
+    pub idat_ids_hash: u64,
+    pub marker_count: usize,                // Probes of the IDAT the bead positions index into
+    pub probes: Vec<SnpProbe>,              // Sorted by AddressA
+    pub addresses: Vec<u32>,                // AddressA of every SNP, sorted
+    pub bead_set_id: Vec<i32>,              // BeadSetID of every entry of addresses
+    pub unique_bead_set_ids: Vec<i32>,
+    pub bead_positions: Vec<BeadPosition>,  // The SNPs found in the IDAT, in SNP order
+    pub beadset_indexes: Vec<(i32, Vec<i32>)>, // Per BeadSetID, the positions of its SNPs in the SNP order
+    pub beadset_ids: Vec<(i32, Vec<u32>)>,  // Per BeadSetID, the AddressA of those SNPs
+}
+
+impl ProbeLayout {
+
+    pub fn build(manifest_path: &str, idat_ids: &[u32], chip_id: &str) -> Result<ProbeLayout> {
+        let probes = read_snp_probes(manifest_path)?;
+        let mut layout = ProbeLayout::from_probes(probes, idat_ids, chip_id);
+        layout.manifest_hash = hash_file(manifest_path)?;
+        Ok(layout)
+    }
+
+    pub fn from_probes(probes: Vec<SnpProbe>, idat_ids: &[u32], chip_id: &str) -> ProbeLayout {
+        let mut layout = ProbeLayout {
+            chip_id: chip_id.to_string(),
+            idat_ids_hash: Self::hash_ids(idat_ids),
+            marker_count: idat_ids.len(),
+            ..Default::default()
+        };
+
+        snp_beadset_addresses(&probes, &mut layout.addresses, &mut layout.bead_set_id, &mut layout.unique_bead_set_ids);
+        layout.bead_positions = locate_beads(&probes, idat_ids);
+
+        layout.probes = probes;
+        layout.group_by_beadset();
+        layout
+    }
+
+    // Lists, per BeadSetID, the positions of its SNPs in the SNP order and their AddressA
+    fn group_by_beadset(&mut self) {
+        let mut indexes: HashMap<i32, Vec<i32>> = HashMap::new();
+        let mut ids: HashMap<i32, Vec<u32>> = HashMap::new();
+        for (snp_index, &(probe_index, _, _)) in self.bead_positions.iter().enumerate() {
+            let probe = &self.probes[probe_index];
+            indexes.entry(probe.bead_set_id).or_default().push(snp_index as i32);
+            ids.entry(probe.bead_set_id).or_default().push(probe.address_a);
+        }
+
+        self.beadset_indexes.clear();
+        self.beadset_ids.clear();
+        for &beadset in &self.unique_bead_set_ids {
+            self.beadset_indexes.push((beadset, indexes.remove(&beadset).unwrap_or_default()));
+            self.beadset_ids.push((beadset, ids.remove(&beadset).unwrap_or_default()));
+        }
+    }
+
+    // True when an IDAT lists the same probes in the same order as the one the layout was built from
+    pub fn matches_ids(&self, idat_ids: &[u32]) -> bool {
+        Self::hash_ids(idat_ids) == self.idat_ids_hash
+    }
+
+    // Finds the beads of the layout SNPs in the IDAT of another sample, None when one of them was not scanned
+    pub fn locate_in(&self, idat_ids: &[u32]) -> Option<Vec<BeadPosition>> {
+        let positions: HashMap<u32, usize> = idat_ids.iter().enumerate().map(|(index, &id)| (id, index)).collect();
+
+        self.bead_positions
+            .iter()
+            .map(|&(probe_index, _, _)| {
+                let probe = &self.probes[probe_index];
+                let bead_a = *positions.get(&probe.address_a)?;
+                let bead_b = match probe.address_b {
+                    Some(address_b) => Some(*positions.get(&address_b)?),
+                    None => None,
+                };
+                Some((probe_index, bead_a, bead_b))
+            })
+            .collect()
+    }
+
+    pub fn hash_ids(idat_ids: &[u32]) -> u64 {
+        let bytes: Vec<u8> = idat_ids.iter().flat_map(|id| id.to_le_bytes()).collect();
+        fnv1a(&bytes)
+    }
+
+    // The cache sits next to the manifest, one file per chip
+    pub fn cache_path(manifest_path: &str, chip_id: &str) -> String {
+        let chip: String = chip_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}.{}.layout", manifest_path, chip)
+    }
+
+    // Loads the cached layout when its manifest hash, chip ID and probe IDs match, otherwise builds and caches it
+    pub fn load_or_build(manifest_path: &str, idat_ids: &[u32], chip_id: &str) -> Result<ProbeLayout> {
+        let manifest_hash = hash_file(manifest_path)?;
+        let cache_path = Self::cache_path(manifest_path, chip_id);
+
+        if let Ok(layout) = Self::read(&cache_path) {
+            if layout.manifest_hash == manifest_hash
+                && layout.chip_id == chip_id
+                && layout.idat_ids_hash == Self::hash_ids(idat_ids)
+                && layout.marker_count == idat_ids.len()
+            {
+                return Ok(layout);
+            }
+        }
+
+        let mut layout = ProbeLayout::from_probes(read_snp_probes(manifest_path)?, idat_ids, chip_id);
+        layout.manifest_hash = manifest_hash;
+
+        // Several ranks may build at once, so each writes its own file and renames it into place
+        let temporary_path = format!("{}.{}.tmp", cache_path, std::process::id());
+        let written = layout.write(&temporary_path).and_then(|_| fs::rename(&temporary_path, &cache_path));
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temporary_path);
+            println!("Could not cache the probe layout at {}: {}", cache_path, err);
+        }
+
+        Ok(layout)
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(LAYOUT_MAGIC)?;
+        writer.write_u32::<LittleEndian>(LAYOUT_VERSION)?;
+        writer.write_u64::<LittleEndian>(self.manifest_hash)?;
+        write_idat_string(writer, &self.chip_id)?;
+        writer.write_u64::<LittleEndian>(self.idat_ids_hash)?;
+        writer.write_u32::<LittleEndian>(self.marker_count as u32)?;
+
+        writer.write_u32::<LittleEndian>(self.probes.len() as u32)?;
+        for probe in &self.probes {
+            write_idat_string(writer, &probe.name)?;
+            writer.write_u32::<LittleEndian>(probe.address_a)?;
+            writer.write_u32::<LittleEndian>(probe.address_b.unwrap_or(NO_BEAD))?;
+            writer.write_u8(assay_code(probe.assay_type))?;
+            writer.write_i32::<LittleEndian>(probe.bead_set_id)?;
+        }
+
+        writer.write_u32::<LittleEndian>(self.bead_positions.len() as u32)?;
+        for &(probe_index, bead_a, bead_b) in &self.bead_positions {
+            writer.write_u32::<LittleEndian>(probe_index as u32)?;
+            writer.write_u32::<LittleEndian>(bead_a as u32)?;
+            writer.write_u32::<LittleEndian>(bead_b.map_or(NO_BEAD, |index| index as u32))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read(path: &str) -> Result<ProbeLayout> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_reader(&mut BufReader::new(file)).map_err(|err| err.in_file(path))
+    }
+
+    // Only the probes and bead positions are stored, the sorted addresses and BeadSetID lists are rebuilt from them
+    // A cache that does not hold together fails with InvalidData, which load_or_build takes as a cache miss
+    pub fn from_reader<R: Read + Seek>(reader: &mut R) -> Result<ProbeLayout> {
+        let mut magic_number = [0u8; 4];
+        reader.read_exact(&mut magic_number)?;
+        if magic_number != LAYOUT_MAGIC[..] {
+            return Err(Error::BadMagic { path: None, format: "probe layout", found: magic_number.to_vec() });
+        }
+
+        let version = reader.read_u32::<LittleEndian>()?;
+        if version != LAYOUT_VERSION {
+            return Err(Error::UnsupportedVersion { path: None, format: "Probe layout", version: version as u64 });
+        }
+
+        let manifest_hash = reader.read_u64::<LittleEndian>()?;
+        let chip_id = read_idat_string(reader)?;
+        let idat_ids_hash = reader.read_u64::<LittleEndian>()?;
+        let marker_count = reader.read_u32::<LittleEndian>()? as usize;
+
+        // A probe takes at least 14 bytes: an empty name, both addresses, the assay type and the BeadSetID
+        let probe_count = reader.read_u32::<LittleEndian>()? as usize;
+        check_remaining(reader, probe_count, 14, "Probe table")?;
+        let mut probes = Vec::with_capacity(probe_count);
+        for _ in 0..probe_count {
+            let name = read_idat_string(reader)?;
+            let address_a = reader.read_u32::<LittleEndian>()?;
+            let address_b = reader.read_u32::<LittleEndian>()?;
+            let assay_type = AssayType::from_bpm(reader.read_u8()?)
+                .ok_or_else(|| invalid_data(format!("Probe {} has an invalid assay type", name)))?;
+            let bead_set_id = reader.read_i32::<LittleEndian>()?;
+            probes.push(SnpProbe {
+                name,
+                address_a,
+                address_b: if address_b == NO_BEAD { None } else { Some(address_b) },
+                assay_type,
+                bead_set_id,
+            });
+        }
+
+        let position_count = reader.read_u32::<LittleEndian>()? as usize;
+        check_remaining(reader, position_count, 12, "Bead position table")?;
+        let mut bead_positions = Vec::with_capacity(position_count);
+        for _ in 0..position_count {
+            let probe_index = reader.read_u32::<LittleEndian>()? as usize;
+            let bead_a = reader.read_u32::<LittleEndian>()? as usize;
+            let bead_b = reader.read_u32::<LittleEndian>()?;
+            if probe_index >= probe_count {
+                return Err(invalid_data(format!("Probe layout refers to probe {} of {}", probe_index, probe_count)));
+            }
+            // The positions index the intensities of every sample, so one past the IDAT would panic there
+            let beads = [Some(bead_a), (bead_b != NO_BEAD).then_some(bead_b as usize)];
+            if let Some(bead) = beads.into_iter().flatten().find(|&bead| bead >= marker_count) {
+                return Err(invalid_data(format!("Probe layout refers to bead {} of an IDAT with {}", bead, marker_count)));
+            }
+            bead_positions.push((probe_index, bead_a, if bead_b == NO_BEAD { None } else { Some(bead_b as usize) }));
+        }
+
+        let mut layout = ProbeLayout {
+            manifest_hash,
+            chip_id,
+            idat_ids_hash,
+            marker_count,
+            bead_positions,
+            ..Default::default()
+        };
+        snp_beadset_addresses(&probes, &mut layout.addresses, &mut layout.bead_set_id, &mut layout.unique_bead_set_ids);
+        layout.probes = probes;
+        layout.group_by_beadset();
+
+        Ok(layout)
+    }
+}
@@ -0,0 +1,54 @@
+mod assay;
+mod bpm;
+mod compatibility;
+mod csv_manifest;
+mod egt;
+mod layout;
+
+pub use assay::{assemble_snp_intensities, locate_beads, AssayType, BeadPosition, SnpProbe};
+pub use bpm::{BeadPoolManifest, LocusEntry};
+pub use compatibility::{CompatibilityReport, DEFAULT_MIN_MATCH_PERCENTAGE};
+pub use csv_manifest::{ControlRecord, CsvManifest, ManifestRecord, SkippedProbe};
+pub use egt::{polar_coordinates, ClusterFile, ClusterRecord, ClusterScore, ClusterStats};
+pub use layout::{hash_file, ProbeLayout};
+
+use crate::error::Result;
+
+// Sorts (address, BeadSetID) pairs by address and writes them into the two parallel vectors
+fn sorted_beadset_addresses(mut combined: Vec<(u32, i32)>, addresses: &mut Vec<u32>, bead_set_id: &mut Vec<i32>) {
+    combined.sort_by_key(|&(address, _)| address);
+
+    addresses.clear();
+    bead_set_id.clear();
+    for (address, beadset_id) in combined {
+        addresses.push(address);
+        bead_set_id.push(beadset_id);
+    }
+}
+
+// Fills the vectors the pipeline groups SNPs with: the AddressA of every SNP and its BeadSetID,
+// in the order of the probes (sorted by AddressA)
+pub fn snp_beadset_addresses(probes: &[SnpProbe], addresses: &mut Vec<u32>, bead_set_id: &mut Vec<i32>, unique_bead_set_ids: &mut Vec<i32>) {
+    let combined: Vec<(u32, i32)> = probes.iter().map(|probe| (probe.address_a, probe.bead_set_id)).collect();
+
+    for &(_, beadset_id) in &combined {
+        if !unique_bead_set_ids.contains(&beadset_id) {
+            unique_bead_set_ids.push(beadset_id);
+        }
+    }
+
+    sorted_beadset_addresses(combined, addresses, bead_set_id);
+}
+
+// Reads the SNP probes from either the binary (.bpm) or the CSV manifest, sorted by AddressA
+pub fn read_snp_probes(manifest_path: &str) -> Result<Vec<SnpProbe>> {
+    if manifest_path.to_lowercase().ends_with(".bpm") {
+        Ok(BeadPoolManifest::read(manifest_path)?.snp_probes())
+    } else {
+        let manifest = CsvManifest::read(manifest_path)?;
+        for probe in &manifest.skipped {
+            println!("Skipping {} on line {} of {}: {}", probe.name, probe.line, manifest_path, probe.reason);
+        }
+        Ok(manifest.snp_probes())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::config::RunConfig;
+use crate::idat_processing::{load_samples, process_sample_record};
+use normalisation::manifest::{read_snp_probes, CompatibilityReport};
+use normalisation::Error;
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// The outcome of checking one sample, an unreadable sample has the error it was read with instead of a report
+struct SampleCheck {
+    sample: String,
+    report: Result<CompatibilityReport, Error>,
+}
+
+impl SampleCheck {
+
+    fn status(&self, min_match_percentage: f64) -> &'static str {
+        match &self.report {
+            Err(_) => "unreadable",
+            Ok(report) if report.is_compatible(min_match_percentage) => "pass",
+            Ok(_) => "fail",
+        }
+    }
+}
+
+fn write_table(path: &Path, checks: &[SampleCheck], min_match_percentage: f64) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "sample\tstatus\tchip_type\tmanifest_addresses\tidat_probes\tmatched_addresses\tmatch_percentage\terror")?;
+    for check in checks {
+        match &check.report {
+            Ok(report) => writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{:.4}\t",
+                check.sample,
+                check.status(min_match_percentage),
+                report.chip_type,
+                report.manifest_addresses,
+                report.idat_probes,
+                report.matched_addresses,
+                report.match_percentage()
+            )?,
+            Err(err) => writeln!(writer, "{}\t{}\t\t\t\t\t\t{}", check.sample, check.status(min_match_percentage), err)?,
+        }
+    }
+    writer.flush()
+}
+
+// Reads the IDATs of every selected sample and checks them against the manifest, without normalising anything
+fn check_samples(config: &RunConfig) -> Result<Vec<SampleCheck>, Error> {
+    let idat_directory = config.idat_directory()?;
+    let (sample_sheet, idat_template) = load_samples(config, 0)?;
+    let probes = read_snp_probes(config.manifest()?)?;
+
+    Ok(sample_sheet
+        .records
+        .par_iter()
+        .map(|record| {
+            let report = process_sample_record(record, idat_directory, &idat_template, 0, 1)
+                .map(|sample| CompatibilityReport::check(&sample.sample, &sample.chip_type, &probes, &sample.ids));
+            SampleCheck { sample: record.sample_id.clone(), report }
+        })
+        .collect())
+}
+
+// Entry point of the qc subcommand, returns the process exit code
+// The checks are written to qc.tsv in the output directory when one is given
+pub fn run(config: &RunConfig) -> i32 {
+    let checks = match check_samples(config) {
+        Ok(checks) => checks,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return 1;
+        }
+    };
+
+    let min_match_percentage = config.normalisation.min_match_percentage;
+    for check in &checks {
+        match &check.report {
+            Ok(report) => println!("{:<10} {}", check.status(min_match_percentage), report),
+            Err(err) => println!("{:<10} {}", check.status(min_match_percentage), err),
+        }
+    }
+
+    let failed: Vec<&str> = checks
+        .iter()
+        .filter(|check| check.status(min_match_percentage) != "pass")
+        .map(|check| check.sample.as_str())
+        .collect();
+    let exit_code = crate::summarise_samples(checks.len(), &failed, "passed", "did not pass");
+
+    if let Some(output_directory) = &config.output_directory {
+        let path = Path::new(output_directory).join("qc.tsv");
+        if let Err(err) = fs::create_dir_all(output_directory).and_then(|_| write_table(&path, &checks, min_match_percentage)) {
+            eprintln!("Error writing {}: {}", path.display(), err);
+            return 1;
+        }
+    }
+
+    exit_code
+}
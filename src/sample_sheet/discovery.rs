@@ -0,0 +1,107 @@
+use super::{Channel, SampleRecord, SampleSheet};
+use crate::error::{invalid_input, Error, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Discovered samples keep the directory of their IDATs in this column
+pub const DIRECTORY_COLUMN: &str = "directory";
+pub const DISCOVERED_TEMPLATE: &str = "{directory}/{barcode}_{position}_{channel}.idat";
+
+// The samples found under an IDAT directory, and the IDATs whose other channel is missing
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscoveredSamples {
+    pub sheet: SampleSheet,
+    pub unpaired: Vec<String>,
+}
+
+// Splits "<barcode>_<position>_<Red|Grn>.idat", optionally gzip-compressed, into its parts
+fn parse_idat_name(file_name: &str) -> Option<(&str, &str, Channel)> {
+    let name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+    let name = name.strip_suffix(".idat")?;
+    let (prefix, channel) = name.rsplit_once('_')?;
+    let channel = match channel {
+        "Red" => Channel::Red,
+        "Grn" => Channel::Green,
+        _ => return None,
+    };
+    let (barcode, position) = prefix.rsplit_once('_')?;
+    if barcode.is_empty() || position.is_empty() {
+        return None;
+    }
+    Some((barcode, position, channel))
+}
+
+fn collect_idats(directory: &Path, found: &mut BTreeMap<(String, String, String), [Option<String>; 2]>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_idats(&path, found)?;
+            continue;
+        }
+
+        let file_name = entry.file_name();
+        let Some((barcode, position, channel)) = file_name.to_str().and_then(parse_idat_name) else {
+            continue;
+        };
+
+        let key = (directory.to_string_lossy().into_owned(), barcode.to_string(), position.to_string());
+        let slot = &mut found.entry(key).or_default()[channel as usize];
+        // A plain IDAT and its compressed copy count once, the reader prefers the plain file
+        if slot.is_none() {
+            *slot = Some(path.to_string_lossy().into_owned());
+        }
+    }
+
+    Ok(())
+}
+
+// Walks an IDAT directory tree and pairs the Red and Grn IDATs of every barcode and position
+// Samples are listed in path order, named "<barcode>_<position>", with their directory made absolute
+// The same barcode and position in two directories fails, as both would be written under one sample ID
+pub fn discover_samples(idat_directory: &str) -> Result<DiscoveredSamples> {
+    let root = fs::canonicalize(idat_directory).map_err(|err| Error::from(err).in_file(idat_directory))?;
+    let mut found = BTreeMap::new();
+    collect_idats(&root, &mut found).map_err(|err| Error::from(err).in_file(idat_directory))?;
+
+    let mut discovered = DiscoveredSamples::default();
+    discovered.sheet.columns = vec![DIRECTORY_COLUMN.to_string()];
+
+    for ((directory, barcode, position), channels) in found {
+        match channels {
+            [Some(_), Some(_)] => {
+                let mut record = SampleRecord {
+                    sentrix_barcode: barcode,
+                    sentrix_position: position,
+                    fields: vec![(DIRECTORY_COLUMN.to_string(), directory)],
+                    ..Default::default()
+                };
+                record.sample_id = record.idat_prefix();
+                discovered.sheet.records.push(record);
+            }
+            [red, green] => discovered.unpaired.extend(red.into_iter().chain(green)),
+        }
+    }
+
+    let mut directories: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for record in &discovered.sheet.records {
+        directories.entry(&record.sample_id).or_default().push(record.field(DIRECTORY_COLUMN).unwrap_or_default());
+    }
+    let duplicates: Vec<String> = directories
+        .into_iter()
+        .filter(|(_, directories)| directories.len() > 1)
+        .map(|(sample_id, directories)| format!("{} in {}", sample_id, directories.join(" and ")))
+        .collect();
+    if !duplicates.is_empty() {
+        return Err(invalid_input(format!(
+            "IDATs of the same sample were found in more than one directory, name them with a sample sheet: {}",
+            duplicates.join("; ")
+        )));
+    }
+
+    Ok(discovered)
+}
@@ -0,0 +1,215 @@
+mod discovery;
+mod path_template;
+mod selection;
+
+pub use discovery::{discover_samples, DiscoveredSamples, DIRECTORY_COLUMN, DISCOVERED_TEMPLATE};
+pub use path_template::{Channel, PathTemplate, BATCH_TEMPLATE, SCANNER_TEMPLATE};
+pub use selection::SampleSelection;
+
+use crate::error::{invalid_data, Error, Result};
+use csv::{ReaderBuilder, StringRecord};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+// One individual of the sample sheet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleRecord {
+    pub sample_id: String,
+    pub sentrix_barcode: String,  // The BeadChip barcode, e.g. "204012345678"
+    pub sentrix_position: String, // The array on the BeadChip, e.g. "R01C01"
+    pub batch: Option<String>,
+    pub fields: Vec<(String, String)>, // Every column of the row, by column name
+    pub line: u64, // Line of the sample sheet, for error messages
+}
+
+impl SampleRecord {
+
+    // The prefix of the IDAT file names of the sample, e.g. "204012345678_R01C01"
+    pub fn idat_prefix(&self) -> String {
+        format!("{}_{}", self.sentrix_barcode, self.sentrix_position)
+    }
+
+    // Looks up the value of any column of the sample sheet row
+    pub fn field(&self, column: &str) -> Option<&str> {
+        self.fields.iter().find(|(name, _)| name == column).map(|(_, value)| value.as_str())
+    }
+}
+
+// The names of the sample sheet columns a SampleRecord is read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    pub sample_id: Option<String>, // Without it the sample is named after its barcode and position
+    pub sentrix_barcode: String,
+    pub sentrix_position: String,
+    pub batch: Option<String>,
+}
+
+impl ColumnMapping {
+
+    // The [Data] section of an Illumina SampleSheet.csv
+    pub fn illumina() -> ColumnMapping {
+        ColumnMapping {
+            sample_id: Some("Sample_ID".to_string()),
+            sentrix_barcode: "SentrixBarcode_A".to_string(),
+            sentrix_position: "SentrixPosition_A".to_string(),
+            batch: None,
+        }
+    }
+
+    // The flat sample sheet the pipeline was first written for
+    pub fn legacy() -> ColumnMapping {
+        ColumnMapping {
+            sample_id: None,
+            sentrix_barcode: "Array Info.S".to_string(),
+            sentrix_position: "Sentrix ID".to_string(),
+            batch: Some("Batch Comment".to_string()),
+        }
+    }
+
+    // Picks the mapping whose barcode column the header has, Illumina first
+    pub fn detect(header: &StringRecord) -> ColumnMapping {
+        let has_column = |name: &str| header.iter().any(|field| field.trim() == name);
+        let illumina = ColumnMapping::illumina();
+        if has_column(&illumina.sentrix_barcode) {
+            illumina
+        } else {
+            ColumnMapping::legacy()
+        }
+    }
+}
+
+// Column positions of the mapping in one header row
+struct Columns {
+    names: Vec<String>,
+    sample_id: Option<usize>,
+    sentrix_barcode: usize,
+    sentrix_position: usize,
+    batch: Option<usize>,
+}
+
+impl Columns {
+
+    fn from_header(header: &StringRecord, mapping: &ColumnMapping) -> Result<Columns> {
+        let positions: HashMap<&str, usize> = header.iter().enumerate().map(|(index, name)| (name.trim(), index)).collect();
+        let required = |name: &str| {
+            positions.get(name).copied().ok_or_else(|| {
+                invalid_data(format!("Sample sheet is missing the {} column", name))
+            })
+        };
+        let optional = |name: &Option<String>| -> Result<Option<usize>> {
+            match name {
+                Some(name) => required(name).map(Some),
+                None => Ok(None),
+            }
+        };
+
+        Ok(Columns {
+            names: header.iter().map(|name| name.trim().to_string()).collect(),
+            sentrix_barcode: required(&mapping.sentrix_barcode)?,
+            sentrix_position: required(&mapping.sentrix_position)?,
+            sample_id: optional(&mapping.sample_id)?,
+            batch: optional(&mapping.batch)?,
+        })
+    }
+
+    fn parse(&self, record: &StringRecord, line: u64) -> Result<SampleRecord> {
+        let text = |index: usize| record.get(index).unwrap_or("").trim().to_string();
+
+        let mut sample = SampleRecord {
+            sample_id: self.sample_id.map(text).unwrap_or_default(),
+            sentrix_barcode: text(self.sentrix_barcode),
+            sentrix_position: text(self.sentrix_position),
+            batch: self.batch.map(text).filter(|batch| !batch.is_empty()),
+            fields: self.names.iter().enumerate().map(|(index, name)| (name.clone(), text(index))).collect(),
+            line,
+        };
+
+        if sample.sentrix_barcode.is_empty() || sample.sentrix_position.is_empty() {
+            return Err(invalid_data(format!("Sample sheet line {}: the Sentrix barcode and position are required", line)));
+        }
+        if sample.sample_id.is_empty() {
+            sample.sample_id = sample.idat_prefix();
+        }
+
+        Ok(sample)
+    }
+}
+
+// A sample sheet, either an Illumina SampleSheet.csv with [Header], [Manifests] and [Data] sections
+// or a flat CSV whose first row names the columns
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleSheet {
+    pub header: Vec<(String, String)>,
+    pub columns: Vec<String>, // The column names of the sample rows
+    pub manifests: Vec<(String, String)>,
+    pub records: Vec<SampleRecord>,
+}
+
+impl SampleSheet {
+
+    pub fn read(path: &str, mapping: Option<&ColumnMapping>) -> Result<SampleSheet> {
+        let file = File::open(path).map_err(|err| Error::from(err).in_file(path))?;
+        Self::from_reader(BufReader::new(file), mapping).map_err(|err| err.in_file(path))
+    }
+
+    // Without a mapping the columns are detected from the header row
+    pub fn from_reader<R: Read>(reader: R, mapping: Option<&ColumnMapping>) -> Result<SampleSheet> {
+        let mut reader = ReaderBuilder::new().has_headers(false).flexible(true).from_reader(reader);
+
+        let mut sheet = SampleSheet::default();
+        let mut section: Option<String> = None; // None until a [Section] line, which flat sample sheets never have
+        let mut columns: Option<Columns> = None;
+
+        for record in reader.records() {
+            let record = record.map_err(|err| invalid_data(format!("Sample sheet is not valid CSV: {}", err)))?;
+            let line = record.position().map_or(0, |position| position.line());
+
+            if record.iter().all(|field| field.trim().is_empty()) {
+                continue;
+            }
+
+            let first = record.get(0).unwrap_or("").trim();
+            if first.starts_with('[') && first.ends_with(']') {
+                section = Some(first.to_string());
+                continue;
+            }
+
+            let key_value = || (first.to_string(), record.get(1).unwrap_or("").trim().to_string());
+            match section.as_deref() {
+                Some("[Header]") => sheet.header.push(key_value()),
+                Some("[Manifests]") => sheet.manifests.push(key_value()),
+                Some("[Data]") | None => match &columns {
+                    Some(columns) => sheet.records.push(columns.parse(&record, line)?),
+                    None => {
+                        let mapping = mapping.cloned().unwrap_or_else(|| ColumnMapping::detect(&record));
+                        let found = Columns::from_header(&record, &mapping)?;
+                        sheet.columns = found.names.clone();
+                        columns = Some(found);
+                    }
+                },
+                Some(_) => {}
+            }
+        }
+
+        if columns.is_none() {
+            return Err(invalid_data("Sample sheet has no header row for its samples".to_string()));
+        }
+
+        Ok(sheet)
+    }
+
+    // Sample sheets with batch comments keep each batch in its own directory, others follow the scanner layout
+    pub fn default_idat_template(&self) -> &'static str {
+        if self.records.iter().any(|record| record.batch.is_some()) {
+            BATCH_TEMPLATE
+        } else {
+            SCANNER_TEMPLATE
+        }
+    }
+
+    // Looks up a value of the [Header] section, e.g. "Investigator Name"
+    pub fn header_value(&self, key: &str) -> Option<&str> {
+        self.header.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+}
@@ -0,0 +1,155 @@
+use super::SampleRecord;
+use crate::error::{invalid_data, invalid_input, Error, Result};
+
+// The layout of the first sites, one "<batch>_iDATS" directory per batch comment
+pub const BATCH_TEMPLATE: &str = "{batch:nospace}_iDATS/{barcode}/{barcode}_{position}_{channel}.idat";
+// The layout written by the Illumina scanner
+pub const SCANNER_TEMPLATE: &str = "{barcode}/{barcode}_{position}_{channel}.idat";
+
+// Placeholders every sample has, other names are sample sheet columns
+const BUILTIN_KEYS: [&str; 5] = ["sample", "barcode", "position", "batch", "channel"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+}
+
+impl Channel {
+
+    // The channel as written in IDAT file names
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Channel::Red => "Red",
+            Channel::Green => "Grn",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    // A placeholder and whether the spaces are removed from its value ("{name:nospace}")
+    Field { key: String, strip_spaces: bool },
+}
+
+// A path with placeholders in braces, e.g. "{barcode}/{barcode}_{position}_{channel}.idat"
+// The placeholders are sample, barcode, position, batch and channel, or the name of any sample sheet column
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl PathTemplate {
+
+    pub fn parse(template: &str) -> Result<PathTemplate> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return Err(invalid_input(format!("IDAT path template {:?} has an unmatched '}}'", template)));
+            }
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+
+            let end = rest[start..].find('}').map(|end| start + end).ok_or_else(|| {
+                invalid_input(format!("IDAT path template {:?} has an unclosed '{{'", template))
+            })?;
+            let placeholder = &rest[start + 1..end];
+            let (key, modifier) = match placeholder.split_once(':') {
+                Some((key, modifier)) => (key, Some(modifier)),
+                None => (placeholder, None),
+            };
+
+            if key.trim().is_empty() || key.contains('{') {
+                return Err(invalid_input(format!("IDAT path template {:?} has an empty or nested placeholder", template)));
+            }
+            let strip_spaces = match modifier {
+                None => false,
+                Some("nospace") => true,
+                Some(modifier) => {
+                    return Err(invalid_input(format!("Unknown modifier {:?} in IDAT path template {:?}", modifier, template)));
+                }
+            };
+
+            segments.push(Segment::Field { key: key.trim().to_string(), strip_spaces });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        let path_template = PathTemplate { template: template.to_string(), segments };
+        if !path_template.keys().any(|key| key == "channel") {
+            return Err(invalid_input(format!("IDAT path template {:?} must contain {{channel}} to tell the Red and Grn files apart", template)));
+        }
+
+        Ok(path_template)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Field { key, .. } => Some(key.as_str()),
+            Segment::Text(_) => None,
+        })
+    }
+
+    // Checks that every placeholder is either built in or a column of the sample sheet
+    pub fn validate(&self, columns: &[String]) -> Result<()> {
+        for key in self.keys() {
+            if !BUILTIN_KEYS.contains(&key) && !columns.iter().any(|column| column == key) {
+                return Err(invalid_input(format!(
+                    "IDAT path template {:?} refers to {{{}}}, which is neither built in ({}) nor a sample sheet column",
+                    self.template,
+                    key,
+                    BUILTIN_KEYS.join(", ")
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // The path of the IDAT of one channel of a sample, relative to the IDAT directory unless the template is absolute
+    pub fn render(&self, record: &SampleRecord, channel: Channel) -> Result<String> {
+        let mut path = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => path.push_str(text),
+                Segment::Field { key, strip_spaces } => {
+                    let value = match key.as_str() {
+                        "sample" => Some(record.sample_id.as_str()),
+                        "barcode" => Some(record.sentrix_barcode.as_str()),
+                        "position" => Some(record.sentrix_position.as_str()),
+                        "batch" => record.batch.as_deref(),
+                        "channel" => Some(channel.suffix()),
+                        column => record.field(column),
+                    };
+                    let value = value.filter(|value| !value.is_empty()).ok_or_else(|| {
+                        invalid_data_for(record, key)
+                    })?;
+
+                    if *strip_spaces {
+                        path.extend(value.chars().filter(|c| *c != ' '));
+                    } else {
+                        path.push_str(value);
+                    }
+                }
+            }
+        }
+        Ok(path)
+    }
+}
+
+fn invalid_data_for(record: &SampleRecord, key: &str) -> Error {
+    invalid_data(format!(
+        "Sample sheet line {}: sample {} has no value for {{{}}} of the IDAT path template",
+        record.line, record.sample_id, key
+    ))
+}
@@ -0,0 +1,83 @@
+use super::SampleRecord;
+use crate::error::{invalid_input, Error, Result};
+use std::collections::HashSet;
+use std::fs;
+
+// Which samples of a sample sheet to process; an empty selection keeps every sample
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleSelection {
+    pub include: Vec<String>,        // Sample IDs to keep, all when empty
+    pub exclude: Vec<String>,        // Sample IDs to leave out
+    pub batches: Vec<String>,        // Batch comments to keep, all when empty
+    pub rows: Option<(usize, usize)>, // First and last sample to keep, counted from 1 in sample sheet order
+}
+
+impl SampleSelection {
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.batches.is_empty() && self.rows.is_none()
+    }
+
+    // Reads a file with one sample ID per line, blank lines and lines starting with '#' are skipped
+    pub fn read_id_file(path: &str) -> Result<Vec<String>> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::from(err).in_file(path))?;
+        Ok(contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect())
+    }
+
+    // Parses a row range such as "1-20", "5-" or "7"
+    pub fn parse_rows(range: &str) -> Result<(usize, usize)> {
+        let invalid = || invalid_input(format!("Row range {:?} is not of the form <first>-<last>, counted from 1", range));
+        let number = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+
+        let (first, last) = match range.split_once('-') {
+            Some((first, last)) if last.trim().is_empty() => (number(first)?, usize::MAX),
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(range)?, number(range)?),
+        };
+        if first == 0 || last < first {
+            return Err(invalid());
+        }
+        Ok((first, last))
+    }
+
+    pub fn is_selected(&self, row: usize, record: &SampleRecord) -> bool {
+        if let Some((first, last)) = self.rows {
+            if row < first || row > last {
+                return false;
+            }
+        }
+        if !self.include.is_empty() && !self.include.contains(&record.sample_id) {
+            return false;
+        }
+        if !self.batches.is_empty() && !record.batch.as_ref().is_some_and(|batch| self.batches.contains(batch)) {
+            return false;
+        }
+        !self.exclude.contains(&record.sample_id)
+    }
+
+    // Keeps the selected samples, in sample sheet order
+    pub fn apply(&self, records: Vec<SampleRecord>) -> Vec<SampleRecord> {
+        records
+            .into_iter()
+            .enumerate()
+            .filter(|(index, record)| self.is_selected(index + 1, record))
+            .map(|(_, record)| record)
+            .collect()
+    }
+
+    // Sample IDs asked for by name that the sample sheet does not have, usually a typo
+    pub fn unknown_ids<'a>(&'a self, records: &[SampleRecord]) -> Vec<&'a str> {
+        let known: HashSet<&str> = records.iter().map(|record| record.sample_id.as_str()).collect();
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .map(String::as_str)
+            .filter(|id| !known.contains(id))
+            .collect()
+    }
+}
@@ -1,11 +1,34 @@
+use crate::apply_normalisation::NormalizationConfig;
+use crate::error::{Error, Result};
 use rayon::prelude::*;
 
+// The later stages fit lines through the points left after removing the outliers
+pub const MIN_POINTS: usize = 5;
 
 pub struct Outliers;
 
 impl Outliers{
 
-    pub fn remove_outliers_parallelised(data: &mut Vec<(f64, f64)>) -> Result<Vec<(usize, (f64, f64))>, i32> {
+    // The values below the lower and above the upper threshold are outliers, values must be sorted
+    // Each threshold is the rank-th smallest (largest) value or the value at the lower (upper) percentile, whichever leaves out fewer
+    fn thresholds(values: &[f64], config: &NormalizationConfig) -> (f64, f64) {
+        let len = values.len();
+        let rank_smallest = values[config.outlier_rank - 1];
+        let rank_largest = values[len - config.outlier_rank];
+        let lower_percentile = values[(config.lower_percentile * len as f64) as usize];
+        let upper_percentile = values[((config.upper_percentile * len as f64) as usize).min(len - 1)];
+        (
+            f64::min(rank_smallest, lower_percentile),
+            f64::max(rank_largest, upper_percentile),
+        )
+    }
+
+    // Removes the outliers from data and returns them with the positions to put them back at
+    // Fails, leaving data unchanged, when there are too few points before or after the outliers are removed
+    pub fn remove_outliers_parallelised(data: &mut Vec<(f64, f64)>, config: &NormalizationConfig) -> Result<Vec<(usize, (f64, f64))>> {
+        if data.len() < config.min_points() {
+            return Err(Error::TooFewPoints { points: data.len(), required: config.min_points() });
+        }
 
         let mut x_values: Vec<f64> = Vec::new();
         let mut y_values: Vec<f64> = Vec::new();
@@ -21,21 +44,9 @@ impl Outliers{
         y_values.par_sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));    
         ratios.par_sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
 
-        let get_thresholds = |values: &Vec<f64>| {
-            let len = values.len();
-            let fifth_smallest = values[4];
-            let fifth_largest    = values[len - 5];
-            let first_percentile = values[(0.01 * len as f64) as usize];
-            let ninety_ninth_percentile = values[(0.99 * len as f64) as usize];
-            (
-                f64::min(fifth_smallest, first_percentile),
-                f64::max(fifth_largest, ninety_ninth_percentile),
-            )
-        };
-        
-        let (x_min, x_max) = get_thresholds(&x_values);
-        let (y_min, y_max) = get_thresholds(&y_values);
-        let (ratio_min, ratio_max) = get_thresholds(&ratios);
+        let (x_min, x_max) = Self::thresholds(&x_values, config);
+        let (y_min, y_max) = Self::thresholds(&y_values, config);
+        let (ratio_min, ratio_max) = Self::thresholds(&ratios, config);
 
         let mut outliers = Vec::new();
         let mut i = 0;
@@ -50,8 +61,12 @@ impl Outliers{
             }
         }
 
-        if data.len() == 0 {
-            return Err(-1);
+        if data.len() < MIN_POINTS {
+            let points = data.len();
+            for &(index, outlier) in outliers.iter().rev() {
+                data.insert(index, outlier);
+            }
+            return Err(Error::TooFewPoints { points, required: MIN_POINTS });
         }
 
         Ok(outliers)
@@ -59,7 +74,7 @@ impl Outliers{
 
 
 
-    pub fn remove_outliers(data: &mut Vec<(f64,f64)>) {
+    pub fn remove_outliers(data: &mut Vec<(f64,f64)>, config: &NormalizationConfig) {
         let mut x_values: Vec<f64> = Vec::with_capacity(data.len());
         let mut y_values: Vec<f64> = Vec::with_capacity(data.len());
         let mut ratios: Vec<f64> = Vec::with_capacity(data.len());
@@ -74,21 +89,9 @@ impl Outliers{
         y_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
         ratios.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
 
-        let get_thresholds = |values: &Vec<f64>| {
-            let len = values.len();
-            let fifth_smallest = values[4];
-            let fifth_largest = values[len - 5];
-            let first_percentile = values[(0.01 * len as f64) as usize];
-            let ninety_ninth_percentile = values[(0.99 * len as f64) as usize];
-            (
-                f64::min(fifth_smallest, first_percentile),
-                f64::max(fifth_largest, ninety_ninth_percentile),
-            )
-        };
-
-        let (x_min, x_max) = get_thresholds(&x_values);
-        let (y_min, y_max) = get_thresholds(&y_values);
-        let (ratio_min, ratio_max) = get_thresholds(&ratios);
+        let (x_min, x_max) = Self::thresholds(&x_values, config);
+        let (y_min, y_max) = Self::thresholds(&y_values, config);
+        let (ratio_min, ratio_max) = Self::thresholds(&ratios, config);
 
         data.retain(|&(x, y)| {
             let ratio = x / (x + y);
@@ -0,0 +1,90 @@
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+impl Axis {
+
+    pub fn value(self, (x, y): (f64, f64)) -> f64 {
+        match self {
+            Axis::X => x,
+            Axis::Y => y,
+        }
+    }
+
+    // The point of data whose value on the axis is nearest to target, by a scan of every point, None when there are none
+    // Distances are compared exactly, and of two points equally near the one earlier in data wins
+    pub fn nearest_in(self, data: &[(f64, f64)], target: f64) -> Option<(f64, f64)> {
+        data.iter().copied().min_by(|&a, &b| compare((self.value(a) - target).abs(), (self.value(b) - target).abs()))
+    }
+}
+
+// Orders values and distances, -0.0 equal to 0.0, with NaN after every number so that a NaN point is never the nearest
+fn compare(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+// The points of a group sorted along one axis, so that the point nearest each sweep position is found by binary search
+// Built once per sweep in O(n log n), each lookup is then O(log n) instead of a scan of the whole group
+// Points with the same value keep their order in the group, and of two points equally near the one earlier in the group wins,
+// as with Axis::nearest_in
+#[derive(Debug, Clone)]
+pub struct SortedAxis {
+    values: Vec<f64>,
+    points: Vec<(f64, f64)>,
+    positions: Vec<usize>, // Position of each point in the group
+}
+
+impl SortedAxis {
+
+    pub fn new(data: &[(f64, f64)], axis: Axis) -> SortedAxis {
+        let mut positions: Vec<usize> = (0..data.len()).collect();
+        // Stable, so equal values stay in group order
+        positions.sort_by(|&a, &b| compare(axis.value(data[a]), axis.value(data[b])));
+
+        SortedAxis {
+            values: positions.iter().map(|&position| axis.value(data[position])).collect(),
+            points: positions.iter().map(|&position| data[position]).collect(),
+            positions,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // The first of the points whose value equals the one at index, which is also the earliest of them in the group
+    fn first_equal(&self, index: usize) -> usize {
+        let value = self.values[index];
+        self.values[..index].partition_point(|&other| compare(other, value) == Ordering::Less)
+    }
+
+    // The point whose value on the axis is nearest to target, None when there are no points
+    pub fn nearest(&self, target: f64) -> Option<(f64, f64)> {
+        let above = self.values.partition_point(|&value| compare(value, target) == Ordering::Less);
+        let below = above.checked_sub(1).map(|index| self.first_equal(index));
+
+        let nearest = match (below, (above < self.len()).then_some(above)) {
+            (Some(below), Some(above)) => {
+                let distance_below = (self.values[below] - target).abs();
+                let distance_above = (self.values[above] - target).abs();
+                match compare(distance_below, distance_above) {
+                    Ordering::Less => below,
+                    Ordering::Greater => above,
+                    Ordering::Equal if self.positions[below] < self.positions[above] => below,
+                    Ordering::Equal => above,
+                }
+            }
+            (Some(index), None) | (None, Some(index)) => index,
+            (None, None) => return None,
+        };
+        Some(self.points[nearest])
+    }
+}
@@ -1,4 +1,5 @@
-use crate::stage2::Translation;
+use crate::apply_normalisation::NormalizationConfig;
+use crate::stage2::{Axis, SortedAxis, Translation};
 use rayon::prelude::*;
 use packed_simd::f64x4;
 
@@ -6,7 +7,7 @@ pub struct Shear;
 
 impl Shear {
 
-    pub fn shear_unparallelised(data: &mut Vec<(f64, f64)>, theta: f64) -> f64 {
+    pub fn shear_unparallelised(data: &mut Vec<(f64, f64)>, theta: f64, config: &NormalizationConfig) -> f64 {
         // Correct for rotation
         for point in data.iter_mut() {
             let temp_x = point.0;
@@ -19,12 +20,12 @@ impl Shear {
         // Y-Sweep for Control Points
         let y_min = data.iter().map(|&(_, y)| y).fold(f64::INFINITY, f64::min);
         let y_max = data.iter().map(|&(_, y)| y).fold(f64::NEG_INFINITY, f64::max);
-        let control_points: Vec<(f64, f64)> = (0..400).map(|i| {
-            let y = y_min + i as f64 * (y_max - y_min) / 399.0;
-            data.iter().cloned().min_by_key(|&(_, y1)| (y1 - y).abs() as i64).unwrap()
+        let control_points: Vec<(f64, f64)> = (0..config.sweep_points).map(|i| {
+            let y = y_min + i as f64 * (y_max - y_min) / (config.sweep_points - 1) as f64;
+            Axis::Y.nearest_in(data, y).unwrap()
         }).collect();
 
-        let (m_shear, _) = Translation::fit_line(&control_points);
+        let (m_shear, _) = Translation::fit_line(&control_points, config.fit_tolerance);
 
         // The angle of this line identifies the shear parameter
         let shear_angle = m_shear.atan();
@@ -32,7 +33,7 @@ impl Shear {
         shear_angle
     }
 
-    pub fn shear_p(data: &mut Vec<(f64, f64)>, theta: f64) -> f64 {
+    pub fn shear_p(data: &mut Vec<(f64, f64)>, theta: f64, config: &NormalizationConfig) -> f64 {
         // Correct for rotation using AVX
         let cos_theta = theta.cos();
         let sin_theta = theta.sin();
@@ -64,13 +65,14 @@ impl Shear {
         // Y-Sweep for Control Points in parallel
         let y_min = data.par_iter().map(|&(_, y)| y).reduce_with(f64::min).unwrap_or(f64::INFINITY);
         let y_max = data.par_iter().map(|&(_, y)| y).reduce_with(f64::max).unwrap_or(f64::NEG_INFINITY);
-        let control_points: Vec<(f64, f64)> = (0..400).into_par_iter()
+        let by_y = SortedAxis::new(data, Axis::Y);
+        let control_points: Vec<(f64, f64)> = (0..config.sweep_points).into_par_iter()
         .map(|i| {
-            let y = y_min + i as f64 * (y_max - y_min) / 399.0;
-            data.par_iter().cloned().min_by_key(|&(_, y1)| (y1 - y).abs() as i64).unwrap()
+            let y = y_min + i as f64 * (y_max - y_min) / (config.sweep_points - 1) as f64;
+            by_y.nearest(y).unwrap()
         }).collect();
     
-        let (m_shear, _) = Translation::fit_line_p(&control_points);
+        let (m_shear, _) = Translation::fit_line_p(&control_points, config.fit_tolerance);
     
         let shear_angle = m_shear.atan();
     
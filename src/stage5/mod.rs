@@ -1,3 +1,5 @@
+use crate::apply_normalisation::NormalizationConfig;
+use crate::stage2::{Axis, SortedAxis};
 use rayon::prelude::*;
 use packed_simd::f64x4;
 
@@ -5,21 +7,23 @@ pub struct Scale;
 
 impl Scale {
     
-    pub fn scale(data: &mut Vec<(f64, f64)>, shear_angle: f64)  {
-    // Correct for shear
+    // Returns the scale factors of the x and y axes, as scale_p does
+    pub fn scale(data: &mut Vec<(f64, f64)>, shear_angle: f64, config: &NormalizationConfig) -> (f64, f64) {
+    // Correct for shear, x3 = x2 - tan(shear) * y2 and y3 = y2
+    let shear = shear_angle.tan();
     for point in data.iter_mut() {
         let temp_x2 = point.0;
         let temp_y2 = point.1;
-        point.0 = temp_x2 - shear_angle * temp_y2; // temp x3
+        point.0 = temp_x2 - shear * temp_y2; // temp x3
         point.1 = temp_y2;                   // temp y3
     }
 
     // X-sweep for virtual points
     let x_min = data.iter().map(|&(x, _)| x).fold(f64::INFINITY, f64::min);
     let x_max = data.iter().map(|&(x, _)| x).fold(f64::NEG_INFINITY, f64::max);
-    let x_virtual_points: Vec<f64> = (0..400).map(|i| {
-        let x = x_min + i as f64 * (x_max - x_min) / 399.0;
-        data.iter().cloned().min_by_key(|&(x1, _)| (x1 - x).abs() as i64).unwrap().0
+    let x_virtual_points: Vec<f64> = (0..config.sweep_points).map(|i| {
+        let x = x_min + i as f64 * (x_max - x_min) / (config.sweep_points - 1) as f64;
+        Axis::X.nearest_in(data, x).unwrap().0
     }).collect();
 
     // Robust mean for scale_x
@@ -28,9 +32,9 @@ impl Scale {
     // Y-sweep for virtual points (triangulation)
     let y_min = data.iter().map(|&(_, y)| y).fold(f64::INFINITY, f64::min);
     let y_max = data.iter().map(|&(_, y)| y).fold(f64::NEG_INFINITY, f64::max);
-    let y_virtual_points: Vec<f64> = (0..400).map(|i| {
-        let y = y_min + i as f64 * (y_max - y_min) / 399.0;
-        data.iter().cloned().min_by_key(|&(_, y1)| (y1 - y).abs() as i64).unwrap().1
+    let y_virtual_points: Vec<f64> = (0..config.sweep_points).map(|i| {
+        let y = y_min + i as f64 * (y_max - y_min) / (config.sweep_points - 1) as f64;
+        Axis::Y.nearest_in(data, y).unwrap().1
     }).collect();
 
     // Robust mean for scale_y
@@ -40,20 +44,24 @@ impl Scale {
         point.0 /= scale_x; // x_n
         point.1 /= scale_y; // y_n
     });
+
+    (scale_x, scale_y)
 }
 
 pub fn robust_mean(values: &Vec<f64>) -> f64 {
     values.iter().sum::<f64>() / values.len() as f64
 }
 
-pub fn scale_p(data: &mut Vec<(f64, f64)>, shear_angle: f64) {
-    // Correct for shear using AVX2
+// Returns the scale factors of the x and y axes
+pub fn scale_p(data: &mut Vec<(f64, f64)>, shear_angle: f64, config: &NormalizationConfig) -> (f64, f64) {
+    // Correct for shear using AVX2, x3 = x2 - tan(shear) * y2 and y3 = y2
     let shear = shear_angle.tan();
-    let shear_values = f64x4::splat(shear);
+    let shear_values = f64x4::new(shear, 0.0, shear, 0.0);
 
     data.par_chunks_mut(2).for_each(|chunk| {
-        let original_x = f64x4::new(chunk[0].0, chunk[0].1, chunk.get(1).map_or(0.0, |p| p.0), chunk.get(1).map_or(0.0, |p| p.1));
-        let corrected = original_x - shear_values * original_x;
+        let original = f64x4::new(chunk[0].0, chunk[0].1, chunk.get(1).map_or(0.0, |p| p.0), chunk.get(1).map_or(0.0, |p| p.1));
+        let swapped = f64x4::new(chunk[0].1, chunk[0].0, chunk.get(1).map_or(0.0, |p| p.1), chunk.get(1).map_or(0.0, |p| p.0));
+        let corrected = original - shear_values * swapped;
         chunk[0].0 = corrected.extract(0);
         chunk[0].1 = corrected.extract(1);
         if let Some(point) = chunk.get_mut(1) {
@@ -65,10 +73,11 @@ pub fn scale_p(data: &mut Vec<(f64, f64)>, shear_angle: f64) {
     let x_min = data.par_iter().map(|&(x, _)| x).reduce_with(f64::min).unwrap_or(f64::INFINITY);
     let x_max = data.par_iter().map(|&(x, _)| x).reduce_with(f64::max).unwrap_or(f64::NEG_INFINITY);
 
-    let x_virtual_points: Vec<f64> = (0..400).into_par_iter()
+    let by_x = SortedAxis::new(data, Axis::X);
+    let x_virtual_points: Vec<f64> = (0..config.sweep_points).into_par_iter()
         .map(|i| {
-            let x = x_min + i as f64 * (x_max - x_min) / 399.0;
-            data.iter().cloned().min_by_key(|&(x1, _)| (x1 - x).abs() as i64).unwrap().1
+            let x = x_min + i as f64 * (x_max - x_min) / (config.sweep_points - 1) as f64;
+            by_x.nearest(x).unwrap().0
         })
         .collect();
 
@@ -76,10 +85,11 @@ pub fn scale_p(data: &mut Vec<(f64, f64)>, shear_angle: f64) {
             // Y-Sweep for Control Points in parallel
     let y_min = data.par_iter().map(|&(_, y)| y).reduce_with(f64::min).unwrap_or(f64::INFINITY);
     let y_max = data.par_iter().map(|&(_, y)| y).reduce_with(f64::max).unwrap_or(f64::NEG_INFINITY);
-    let y_virtual_points: Vec<f64> = (0..400).into_par_iter()
+    let by_y = SortedAxis::new(data, Axis::Y);
+    let y_virtual_points: Vec<f64> = (0..config.sweep_points).into_par_iter()
     .map(|i| {
-        let y = y_min + i as f64 * (y_max - y_min) / 399.0;
-        data.par_iter().cloned().min_by_key(|&(_, y1)| (y1 - y).abs() as i64).unwrap().1
+        let y = y_min + i as f64 * (y_max - y_min) / (config.sweep_points - 1) as f64;
+        by_y.nearest(y).unwrap().1
     }).collect();
 
     let scale_y = Self::parallel_mean(&y_virtual_points);
@@ -96,6 +106,7 @@ pub fn scale_p(data: &mut Vec<(f64, f64)>, shear_angle: f64) {
         }
     });
 
+    (scale_x, scale_y)
 }
 
 pub fn parallel_mean(values: &Vec<f64>) -> f64 {
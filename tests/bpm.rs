@@ -0,0 +1,18 @@
+use normalisation::idat::write_idat_string;
+use normalisation::manifest::BeadPoolManifest;
+use normalisation::Error;
+use std::io::Cursor;
+
+#[test]
+fn corrupt_locus_counts_fail_without_allocating_them() {
+    let mut bytes = b"BPM\x01".to_vec();
+    bytes.extend(4u32.to_le_bytes());
+    write_idat_string(&mut bytes, "Demo_A1").unwrap();
+    write_idat_string(&mut bytes, "").unwrap();
+    bytes.extend(u32::MAX.to_le_bytes());
+
+    match BeadPoolManifest::from_reader(&mut Cursor::new(&bytes)) {
+        Err(Error::InvalidData { message, .. }) => assert!(message.contains("Locus table"), "{}", message),
+        other => panic!("expected InvalidData, got {:?}", other.map(|manifest| manifest.loci.len())),
+    }
+}
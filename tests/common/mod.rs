@@ -0,0 +1,30 @@
+// Builders shared by the integration tests, each test binary uses some of them
+#![allow(dead_code)]
+
+use normalisation::manifest::{AssayType, SnpProbe};
+
+// count points evenly spaced along a homozygote arm or a cluster, at point(0), point(1 / (count - 1)) ... point(1)
+// The point farthest out comes first, which is the one a sweep comparing truncated distances would pick every time
+pub fn arm(count: usize, point: impl Fn(f64) -> (f64, f64)) -> Vec<(f64, f64)> {
+    (0..count).rev().map(|i| point(i as f64 / (count - 1) as f64)).collect()
+}
+
+// Two homozygote clusters along the axes and a heterozygote cluster between them, at raw intensities in the thousands
+// Each cluster is a short arm pointing away from the origin, with the points of the three interleaved
+pub fn genotype_clusters() -> Vec<(f64, f64)> {
+    let homozygote_a = arm(20, |t| (4000.0 + 400.0 * t, 300.0 + 80.0 * t));
+    let homozygote_b = arm(20, |t| (250.0 + 80.0 * t, 3600.0 + 320.0 * t));
+    let heterozygote = arm(20, |t| (2100.0 + 240.0 * t, 1900.0 + 160.0 * t));
+    (0..20).flat_map(|i| [homozygote_a[i], homozygote_b[i], heterozygote[i]]).collect()
+}
+
+// An Infinium II probe, or an Infinium I probe when it has a B address
+pub fn probe(name: &str, address_a: u32, address_b: Option<u32>, bead_set_id: i32) -> SnpProbe {
+    SnpProbe {
+        name: name.to_string(),
+        address_a,
+        address_b,
+        assay_type: if address_b.is_some() { AssayType::InfiniumIRed } else { AssayType::InfiniumII },
+        bead_set_id,
+    }
+}
@@ -0,0 +1,63 @@
+use normalisation::manifest::{assemble_snp_intensities, CompatibilityReport, ProbeLayout};
+use normalisation::Error;
+use std::io::Cursor;
+
+mod common;
+use common::probe;
+
+#[test]
+fn missing_and_extra_addresses_are_reported() {
+    let probes = vec![probe("rs1", 10, None, 1), probe("rs2", 20, Some(21), 2), probe("rs3", 30, None, 1)];
+    let report = CompatibilityReport::check("S1_R01C01", "GSA-24v3-0", &probes, &[10, 20, 30, 99]);
+
+    assert_eq!(report.manifest_addresses, 4);
+    assert_eq!(report.matched_addresses, 3);
+    assert_eq!(report.missing_addresses, vec![21]);
+    assert_eq!(report.extra_addresses, vec![99]);
+    assert_eq!(report.match_percentage(), 75.0);
+    assert!(report.is_compatible(75.0));
+    assert!(!report.is_compatible(99.0));
+}
+
+#[test]
+fn layout_beads_are_located_in_a_reordered_idat() {
+    let probes = vec![probe("rs1", 10, None, 1), probe("rs2", 20, Some(21), 2)];
+    let layout = ProbeLayout::from_probes(probes, &[10, 20, 21], "chip");
+
+    assert!(layout.matches_ids(&[10, 20, 21]));
+    assert!(!layout.matches_ids(&[21, 20, 10]));
+    assert_eq!(layout.locate_in(&[21, 20, 10]), Some(vec![(0, 2, None), (1, 1, Some(0))]));
+    assert_eq!(layout.locate_in(&[20, 10]), None);
+}
+
+#[test]
+fn corrupt_layout_caches_are_rejected() {
+    let probes = vec![probe("rs1", 10, None, 1), probe("rs2", 20, Some(21), 2)];
+    let layout = ProbeLayout::from_probes(probes, &[10, 20, 21], "chip");
+    let mut bytes = Vec::new();
+    layout.write_to(&mut bytes).unwrap();
+    assert_eq!(ProbeLayout::from_reader(&mut Cursor::new(&bytes)).unwrap(), layout);
+
+    // A bead past the end of the IDAT the layout was built from, the A bead of the first of the two positions at the end
+    let mut corrupt = bytes.clone();
+    let bead_a = corrupt.len() - 20;
+    corrupt[bead_a..bead_a + 4].copy_from_slice(&3u32.to_le_bytes());
+    assert!(matches!(ProbeLayout::from_reader(&mut Cursor::new(&corrupt)), Err(Error::InvalidData { .. })));
+
+    // A probe count far beyond the file, after the magic, version, manifest hash, chip ID, probe ID hash and marker count
+    let mut corrupt = bytes;
+    corrupt[33..37].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(ProbeLayout::from_reader(&mut Cursor::new(&corrupt)), Err(Error::InvalidData { .. })));
+}
+
+#[test]
+fn every_bead_position_gives_one_snp() {
+    let probes = vec![probe("rs1", 10, None, 1), probe("rs2", 20, Some(21), 2), probe("rs3", 30, None, 1)];
+    let data = [(100.0, 200.0), (300.0, 400.0), (500.0, 600.0), (700.0, 800.0)];
+    let positions = vec![(0, 0, None), (1, 1, Some(2)), (2, 3, None)];
+    assert_eq!(assemble_snp_intensities(&probes, &positions, &data).unwrap(), vec![(100.0, 200.0), (300.0, 500.0), (700.0, 800.0)]);
+
+    // An Infinium I SNP without its B bead fails rather than shifting rs3 into its place
+    let positions = vec![(0, 0, None), (1, 1, None), (2, 3, None)];
+    assert!(matches!(assemble_snp_intensities(&probes, &positions, &data), Err(Error::InvalidData { .. })));
+}
@@ -0,0 +1,65 @@
+use normalisation::manifest::{AssayType, CsvManifest};
+
+const MANIFEST: &str = "Illumina, Inc.,,,,
+[Heading],,,,
+Descriptor File Name,Demo_A1.bpm,,,
+Loci Count ,3,,,
+[Assay],,,,
+IlmnID,Name,IlmnStrand,SNP,AddressA_ID,AlleleA_ProbeSeq,AddressB_ID,Chr,MapInfo,BeadSetID,SourceSeq
+rs1-131_T_F_2,rs1,TOP,[A/G],0010,\"ACGT,TT\",,1,1000,7,
+rs2-131_B_R_2,rs2,BOT,[T/A],0030,ACGA,0020,2,2000,3,TTACG[T/A]TCC
+rs3-131_T_F_2,rs3,TOP,[A/C],0005,TTGA,,X,3000,7,
+[Controls],,,,
+0027630314,Staining,Red,DNP (High),
+";
+
+#[test]
+fn sections_and_quoted_fields_are_parsed() {
+    let manifest = CsvManifest::from_reader(MANIFEST.as_bytes()).unwrap();
+
+    assert_eq!(manifest.heading_value("Descriptor File Name"), Some("Demo_A1.bpm"));
+    assert_eq!(manifest.records.len(), 3);
+    assert_eq!(manifest.records[0].address_a, 10);
+    assert_eq!(manifest.records[1].address_b, Some(20));
+    assert_eq!(manifest.records[1].alleles(), Some(("T".to_string(), "A".to_string())));
+    assert_eq!(manifest.records[1].assay_type, AssayType::InfiniumIRed);
+    assert!(manifest.skipped.is_empty());
+    assert_eq!(manifest.controls.len(), 1);
+    assert_eq!(manifest.controls[0].control_type, "Staining");
+
+    let (mut addresses, mut bead_set_id, mut unique) = (Vec::new(), Vec::new(), Vec::new());
+    manifest.beadset_addresses(&mut addresses, &mut bead_set_id, &mut unique);
+    assert_eq!(addresses, vec![5, 10, 20, 30]);
+    assert_eq!(bead_set_id, vec![7, 7, 3, 3]);
+    assert_eq!(unique, vec![7, 3]);
+}
+
+#[test]
+fn missing_column_is_reported() {
+    let manifest = MANIFEST.replace("BeadSetID", "BeadSet");
+    let err = CsvManifest::from_reader(manifest.as_bytes()).unwrap_err();
+    assert!(err.to_string().contains("BeadSetID"));
+}
+
+#[test]
+fn infinium_i_channels_come_from_the_manifest() {
+    let manifest = "[Assay]
+IlmnID,Name,SNP,AddressA_ID,AlleleA_ProbeSeq,AddressB_ID,BeadSetID,SourceSeq,Color_Channel
+rs1,rs1,[A/G],0010,GGAT,0011,1,CC[A/G]TCC,
+rs2,rs2,[A/T],0020,,0021,1,,Grn
+rs3,rs3,[D/I],0030,AAAA,0031,1,CCGG[-/TT]GGCC,
+rs4,rs4,[A/C],0040,TTGA,,1,,
+";
+    let manifest = CsvManifest::from_reader(manifest.as_bytes()).unwrap();
+
+    // rs1 lies on the other strand of its sequence, so it is extended with the complement of the C before the variant
+    assert_eq!(manifest.records.len(), 3);
+    assert_eq!(manifest.records[0].assay_type, AssayType::InfiniumIGreen);
+    assert_eq!(manifest.records[1].assay_type, AssayType::InfiniumIGreen);
+    assert_eq!(manifest.records[2].assay_type, AssayType::InfiniumII);
+
+    // The indel is left out on its own instead of failing the manifest
+    assert_eq!(manifest.skipped.len(), 1);
+    assert_eq!(manifest.skipped[0].name, "rs3");
+    assert_eq!(manifest.skipped[0].line, 5);
+}
@@ -0,0 +1,119 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use normalisation::idat::write_idat_string;
+use normalisation::manifest::{polar_coordinates, ClusterFile};
+
+mod common;
+use common::probe;
+
+// (name, address, counts, theta means, GenTrain score)
+type Snp = (&'static str, u32, [u32; 3], [f32; 3], f32);
+
+const SNPS: [Snp; 2] = [
+    ("rs1", 10, [120, 300, 80], [0.05, 0.5, 0.95], 0.91),
+    ("rs2", 20, [0, 40, 460], [0.1, 0.45, 0.9], 0.62),
+];
+
+fn cluster_file_bytes(data_block_version: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.write_u32::<LittleEndian>(3).unwrap();
+    for text in ["7.0.0", "7.0.0", "7.0.0", "1.1.0", "1/2/2024 10:00 AM"] {
+        write_idat_string(&mut bytes, text).unwrap();
+    }
+    bytes.write_u8(1).unwrap();
+    write_idat_string(&mut bytes, "Demo_A1.bpm").unwrap();
+    bytes.write_u32::<LittleEndian>(data_block_version).unwrap();
+    write_idat_string(&mut bytes, "").unwrap();
+
+    bytes.write_u32::<LittleEndian>(SNPS.len() as u32).unwrap();
+    for (_, _, counts, theta_means, _) in SNPS {
+        let mut values: Vec<f32> = Vec::new();
+        values.extend([0.1, 0.2, 0.3]); // R deviations
+        values.extend([1.0, 1.5, 2.0]); // R means
+        values.extend([0.01, 0.02, 0.03]); // Theta deviations
+        values.extend(theta_means);
+        if data_block_version >= 9 {
+            values.push(0.2);
+        }
+        values.extend([0.0; 14]);
+
+        for count in counts {
+            bytes.write_u32::<LittleEndian>(count).unwrap();
+        }
+        for value in values {
+            bytes.write_f32::<LittleEndian>(value).unwrap();
+        }
+    }
+
+    for (_, _, _, _, gentrain) in SNPS {
+        for value in [0.8, gentrain, 0.7] {
+            bytes.write_f32::<LittleEndian>(value).unwrap();
+        }
+        bytes.write_u8(0).unwrap();
+    }
+    for _ in SNPS {
+        write_idat_string(&mut bytes, "").unwrap();
+    }
+    for (name, _, _, _, _) in SNPS {
+        write_idat_string(&mut bytes, name).unwrap();
+    }
+    for (_, address, _, _, _) in SNPS {
+        bytes.write_u32::<LittleEndian>(address).unwrap();
+    }
+    for (_, _, counts, _, _) in SNPS {
+        for count in counts {
+            bytes.write_u32::<LittleEndian>(count).unwrap();
+        }
+    }
+    bytes
+}
+
+#[test]
+fn cluster_statistics_are_keyed_by_snp_name() {
+    for data_block_version in [8, 9] {
+        let bytes = cluster_file_bytes(data_block_version);
+        let clusters = ClusterFile::from_reader(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(clusters.manifest_name, "Demo_A1.bpm");
+        assert_eq!(clusters.records.len(), 2);
+
+        let rs2 = clusters.get("rs2").unwrap();
+        assert_eq!(rs2.address, 20);
+        assert_eq!(rs2.cluster_counts(), (0, 40, 460));
+        assert_eq!(rs2.ab.theta_mean, 0.45);
+        assert_eq!(rs2.bb.r_mean, 2.0);
+        assert_eq!(rs2.gentrain_score(), 0.62);
+        assert_eq!(rs2.intensity_threshold.is_some(), data_block_version == 9);
+    }
+
+    let bytes = cluster_file_bytes(9);
+    let clusters = ClusterFile::from_reader(&mut bytes.as_slice()).unwrap();
+    let records = clusters.records_for(&[probe("rs1", 0, None, 1), probe("rs9", 0, None, 1)]);
+    assert_eq!(records[0].map(|record| record.address), Some(10));
+    assert!(records[1].is_none());
+}
+
+#[test]
+fn mismatched_cluster_counts_are_rejected() {
+    let mut bytes = cluster_file_bytes(8);
+    let last = bytes.len() - 4;
+    bytes[last] = 1; // Second copy of the BB count of rs2
+    assert!(ClusterFile::from_reader(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn corrupt_record_counts_fail_without_allocating_them() {
+    // Four billion records in a file of a few hundred bytes, the count follows the header strings and the data block version
+    let mut bytes = cluster_file_bytes(8);
+    let offset = 64;
+    assert_eq!(bytes[offset..offset + 4], (SNPS.len() as u32).to_le_bytes());
+    bytes[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(ClusterFile::from_reader(&mut bytes.as_slice()).is_err());
+}
+
+#[test]
+fn intensities_convert_to_theta_and_r() {
+    let (theta, r) = polar_coordinates(1.0, 1.0);
+    assert!((theta - 0.5).abs() < 1e-12);
+    assert_eq!(r, 2.0);
+    assert_eq!(polar_coordinates(2.0, 0.0), (0.0, 2.0));
+}
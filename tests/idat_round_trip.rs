@@ -0,0 +1,167 @@
+use normalisation::idat::{validate_channel_pair, ChannelMismatch, IdatFile, MappedIdat, RunInfo, FID_BARCODE, FID_N_SNPS_READ};
+use normalisation::Error;
+use std::io::Cursor;
+
+fn synthetic_idat(means: Vec<u16>) -> IdatFile {
+    let mut idat = IdatFile::new(vec![10000011, 10000024, 10000037, 10000052], means);
+    idat.std_devs = vec![31, 42, 17, 256];
+    idat.n_beads = vec![12, 15, 9, 20];
+    idat.mid_block = vec![10000011, 10000024, 10000037, 10000052];
+    idat.red_green = Some(0);
+    idat.manifest = Some(String::new());
+    idat.barcode = Some("200123450001".to_string());
+    idat.chip_type = Some("BeadChip 12x1".to_string());
+    idat.sentrix_position = Some("R01C01".to_string());
+    idat.run_info = vec![RunInfo {
+        run_time: "1/1/2020 10:00:00 AM".to_string(),
+        block_type: "Scan".to_string(),
+        block_pars: "DemoScanner".to_string(),
+        block_code: "ScanCode".to_string(),
+        code_version: "1.0.0".to_string(),
+    }];
+    idat.unknown_strings = vec![(406, "a".repeat(300))];
+    idat
+}
+
+fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("{}_{}", std::process::id(), name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn written_idat_reads_back_identically() {
+    let mut expected = synthetic_idat(vec![1200, 340, 5600, 78]);
+    let bytes = expected.to_bytes().unwrap();
+    let read_back = IdatFile::from_reader(&mut Cursor::new(bytes)).unwrap();
+
+    expected.field_offsets = expected.write_to(&mut Vec::new()).unwrap();
+    assert_eq!(read_back, expected);
+}
+
+#[test]
+fn plain_and_gzip_files_map_to_the_same_values() {
+    let idat = synthetic_idat(vec![1200, 340, 5600, 78]);
+
+    for name in ["fixture_Red.idat", "fixture_Red.idat.gz"] {
+        let path = temp_path(name);
+        idat.write(&path).unwrap();
+
+        let mapped = MappedIdat::open(&path).unwrap();
+        assert_eq!(mapped.num_markers(), 4);
+        assert_eq!(mapped.ids().collect::<Vec<u32>>(), idat.illumina_ids);
+        assert_eq!(mapped.means().collect::<Vec<u16>>(), idat.means);
+        assert_eq!(mapped.barcode().as_deref(), Some("200123450001"));
+        assert_eq!(IdatFile::read(&path).unwrap().n_beads, idat.n_beads);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn channels_from_different_positions_are_rejected() {
+    let red = synthetic_idat(vec![1200, 340, 5600, 78]);
+    let mut green = synthetic_idat(vec![80, 4500, 300, 1100]);
+
+    let red_path = temp_path("pair_Red.idat");
+    let grn_path = temp_path("pair_Grn.idat");
+    red.write(&red_path).unwrap();
+    green.write(&grn_path).unwrap();
+    let (red_idat, grn_idat) = (MappedIdat::open(&red_path).unwrap(), MappedIdat::open(&grn_path).unwrap());
+    assert!(validate_channel_pair("sample", &red_idat, &grn_idat).is_ok());
+
+    green.sentrix_position = Some("R02C01".to_string());
+    green.write(&grn_path).unwrap();
+    let grn_idat = MappedIdat::open(&grn_path).unwrap();
+    assert!(matches!(
+        validate_channel_pair("sample", &red_idat, &grn_idat),
+        Err(ChannelMismatch::SentrixPosition { .. })
+    ));
+
+    std::fs::remove_file(&red_path).unwrap();
+    std::fs::remove_file(&grn_path).unwrap();
+}
+
+#[test]
+fn corrupt_headers_are_reported_with_the_file() {
+    let path = temp_path("corrupt_Red.idat");
+    let mut bytes = synthetic_idat(vec![1200, 340, 5600, 78]).to_bytes().unwrap();
+
+    bytes[4] = 2; // Version 2
+    std::fs::write(&path, &bytes).unwrap();
+    match MappedIdat::open(&path) {
+        Err(Error::UnsupportedVersion { path: Some(file), version: 2, .. }) => assert_eq!(file, path),
+        other => panic!("expected an unsupported version, got {:?}", other.err()),
+    }
+
+    bytes[..4].copy_from_slice(b"BPM\x01");
+    std::fs::write(&path, &bytes).unwrap();
+    match IdatFile::read(&path) {
+        Err(Error::BadMagic { path: Some(file), format: "IDAT", .. }) => assert_eq!(file, path),
+        other => panic!("expected a bad magic number, got {:?}", other.err()),
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn empty_and_truncated_files_are_reported_with_the_file() {
+    let path = temp_path("truncated_Red.idat");
+    let bytes = synthetic_idat(vec![1200, 340, 5600, 78]).to_bytes().unwrap();
+
+    for length in [0, 10] {
+        std::fs::write(&path, &bytes[..length]).unwrap();
+        match MappedIdat::open(&path) {
+            Err(Error::InvalidData { path: Some(file), message }) => {
+                assert_eq!(file, path);
+                assert!(message.starts_with(&format!("IDAT file is {} bytes", length)), "{}", message);
+            }
+            other => panic!("expected invalid data, got {:?}", other.err()),
+        }
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_lengths_fail_without_allocating_them() {
+    let bytes = synthetic_idat(vec![1200, 340, 5600, 78]).to_bytes().unwrap();
+    let idat = IdatFile::from_reader(&mut Cursor::new(&bytes)).unwrap();
+
+    // Four billion markers in a file of a few hundred bytes
+    let mut corrupt = bytes.clone();
+    let offset = idat.field_offset(FID_N_SNPS_READ).unwrap() as usize;
+    corrupt[offset..offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    match IdatFile::from_reader(&mut Cursor::new(&corrupt)) {
+        Err(Error::InvalidData { message, .. }) => assert!(message.starts_with("Illumina ID array"), "{}", message),
+        other => panic!("expected invalid data, got {:?}", other),
+    }
+
+    // A string length prefix of about 2^35 bytes
+    let mut corrupt = bytes.clone();
+    let offset = idat.field_offset(FID_BARCODE).unwrap() as usize;
+    corrupt[offset..offset + 5].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x7f]);
+    assert!(matches!(IdatFile::from_reader(&mut Cursor::new(&corrupt)), Err(Error::InvalidData { .. })));
+}
+
+#[test]
+fn field_offsets_past_the_end_are_rejected() {
+    let path = temp_path("offset_Red.idat");
+    let mut bytes = synthetic_idat(vec![1200, 340, 5600, 78]).to_bytes().unwrap();
+    let idat = IdatFile::from_reader(&mut Cursor::new(&bytes)).unwrap();
+
+    // Each field table entry is a u16 code and a u64 offset, after the magic number, version and field count
+    let entry = idat.field_offsets.iter().position(|&(code, _)| code == FID_N_SNPS_READ).unwrap();
+    let offset = 16 + entry * 10 + 2;
+    for corrupt in [u64::MAX - 1, bytes.len() as u64 - 2] {
+        bytes[offset..offset + 8].copy_from_slice(&corrupt.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        match MappedIdat::open(&path) {
+            Err(Error::InvalidData { path: Some(file), .. }) => assert_eq!(file, path),
+            other => panic!("expected invalid data, got {:?}", other.err()),
+        }
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,158 @@
+use normalisation::sample_sheet::{
+    discover_samples, Channel, ColumnMapping, PathTemplate, SampleSelection, SampleSheet, DISCOVERED_TEMPLATE,
+};
+
+const ILLUMINA: &str = "[Header],,,
+Investigator Name,\"Smith, J\",,
+Project Name,Cohort 1,,
+[Manifests],,,
+A,GSA-24v3-0_A1.bpm,,
+[Data],,,
+Sample_ID,SentrixBarcode_A,SentrixPosition_A,Sample_Plate
+S001,204012345678,R01C01,\"Plate 1, left\"
+S002,204012345678,R02C01,Plate 1
+,,,
+";
+
+const LEGACY: &str = "Sample Name,Batch Comment,Array Info.S,Sentrix ID
+NA12878,Batch 3,204012345678,R01C01
+";
+
+#[test]
+fn illumina_sections_are_parsed() {
+    let sheet = SampleSheet::from_reader(ILLUMINA.as_bytes(), None).unwrap();
+
+    assert_eq!(sheet.header_value("Investigator Name"), Some("Smith, J"));
+    assert_eq!(sheet.manifests, vec![("A".to_string(), "GSA-24v3-0_A1.bpm".to_string())]);
+    assert_eq!(sheet.records.len(), 2);
+    assert_eq!(sheet.records[1].sample_id, "S002");
+    assert_eq!(sheet.records[1].idat_prefix(), "204012345678_R02C01");
+    assert_eq!(sheet.records[1].batch, None);
+}
+
+#[test]
+fn flat_sample_sheets_use_the_legacy_columns() {
+    let sheet = SampleSheet::from_reader(LEGACY.as_bytes(), None).unwrap();
+
+    assert_eq!(sheet.records.len(), 1);
+    assert_eq!(sheet.records[0].sentrix_barcode, "204012345678");
+    assert_eq!(sheet.records[0].sentrix_position, "R01C01");
+    assert_eq!(sheet.records[0].batch.as_deref(), Some("Batch 3"));
+    assert_eq!(sheet.records[0].sample_id, "204012345678_R01C01");
+}
+
+#[test]
+fn columns_can_be_mapped() {
+    let mapping = ColumnMapping {
+        sample_id: Some("Sample Name".to_string()),
+        ..ColumnMapping::legacy()
+    };
+    let sheet = SampleSheet::from_reader(LEGACY.as_bytes(), Some(&mapping)).unwrap();
+    assert_eq!(sheet.records[0].sample_id, "NA12878");
+
+    let error = SampleSheet::from_reader(LEGACY.as_bytes(), Some(&ColumnMapping::illumina())).unwrap_err();
+    assert!(error.to_string().contains("SentrixBarcode_A"));
+}
+
+#[test]
+fn idat_paths_follow_the_template() {
+    let sheet = SampleSheet::from_reader(LEGACY.as_bytes(), None).unwrap();
+    let record = &sheet.records[0];
+
+    let template = PathTemplate::parse(sheet.default_idat_template()).unwrap();
+    template.validate(&sheet.columns).unwrap();
+    assert_eq!(
+        template.render(record, Channel::Red).unwrap(),
+        "Batch3_iDATS/204012345678/204012345678_R01C01_Red.idat"
+    );
+
+    let template = PathTemplate::parse("/scans/{Sample Name}/{barcode}_{position}_{channel}.idat").unwrap();
+    template.validate(&sheet.columns).unwrap();
+    assert_eq!(template.render(record, Channel::Green).unwrap(), "/scans/NA12878/204012345678_R01C01_Grn.idat");
+}
+
+#[test]
+fn invalid_templates_are_rejected() {
+    let sheet = SampleSheet::from_reader(LEGACY.as_bytes(), None).unwrap();
+
+    assert!(PathTemplate::parse("{barcode}/{barcode}_{position}.idat").is_err());
+    assert!(PathTemplate::parse("{barcode/{channel}.idat").is_err());
+    assert!(PathTemplate::parse("{barcode:upper}_{channel}.idat").is_err());
+
+    let template = PathTemplate::parse("{Plate}/{barcode}_{channel}.idat").unwrap();
+    assert!(template.validate(&sheet.columns).is_err());
+}
+
+#[test]
+fn idat_pairs_are_discovered() {
+    let root = std::env::temp_dir().join(format!("discover_samples_{}", std::process::id()));
+    let batch = root.join("batch 1").join("204012345678");
+    std::fs::create_dir_all(&batch).unwrap();
+    for name in [
+        "204012345678_R01C01_Red.idat",
+        "204012345678_R01C01_Grn.idat.gz",
+        "204012345678_R02C01_Red.idat",
+        "notes.txt",
+    ] {
+        std::fs::write(batch.join(name), b"").unwrap();
+    }
+
+    let discovered = discover_samples(root.to_str().unwrap()).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(discovered.sheet.records.len(), 1);
+    let record = &discovered.sheet.records[0];
+    assert_eq!(record.sample_id, "204012345678_R01C01");
+    assert!(discovered.unpaired[0].ends_with("204012345678_R02C01_Red.idat"));
+
+    let template = PathTemplate::parse(DISCOVERED_TEMPLATE).unwrap();
+    template.validate(&discovered.sheet.columns).unwrap();
+    let red = template.render(record, Channel::Red).unwrap();
+    assert!(red.starts_with('/') && red.ends_with("batch 1/204012345678/204012345678_R01C01_Red.idat"));
+}
+
+#[test]
+fn the_same_sample_in_two_directories_is_reported() {
+    let root = std::env::temp_dir().join(format!("discover_duplicates_{}", std::process::id()));
+    for batch in ["batch 1", "batch 2"] {
+        let directory = root.join(batch);
+        std::fs::create_dir_all(&directory).unwrap();
+        for name in ["204012345678_R01C01_Red.idat", "204012345678_R01C01_Grn.idat"] {
+            std::fs::write(directory.join(name), b"").unwrap();
+        }
+    }
+
+    let error = discover_samples(root.to_str().unwrap()).unwrap_err();
+    std::fs::remove_dir_all(&root).unwrap();
+
+    let message = error.to_string();
+    assert!(message.contains("204012345678_R01C01 in "), "{}", message);
+    assert!(message.contains("batch 1") && message.contains("batch 2"), "{}", message);
+}
+
+#[test]
+fn samples_are_selected() {
+    let sheet = SampleSheet::from_reader(ILLUMINA.as_bytes(), None).unwrap();
+    let ids = |selection: &SampleSelection| -> Vec<String> {
+        selection.apply(sheet.records.clone()).into_iter().map(|record| record.sample_id).collect()
+    };
+
+    assert!(SampleSelection::default().is_empty());
+    assert_eq!(ids(&SampleSelection::default()), vec!["S001", "S002"]);
+
+    let by_id = SampleSelection { include: vec!["S002".to_string(), "S404".to_string()], ..Default::default() };
+    assert_eq!(ids(&by_id), vec!["S002"]);
+    assert_eq!(by_id.unknown_ids(&sheet.records), vec!["S404"]);
+
+    let excluded = SampleSelection { exclude: vec!["S001".to_string()], ..Default::default() };
+    assert_eq!(ids(&excluded), vec!["S002"]);
+
+    let by_row = SampleSelection { rows: Some(SampleSelection::parse_rows("1").unwrap()), ..Default::default() };
+    assert_eq!(ids(&by_row), vec!["S001"]);
+    assert_eq!(SampleSelection::parse_rows("2-").unwrap(), (2, usize::MAX));
+    assert!(SampleSelection::parse_rows("0-3").is_err());
+    assert!(SampleSelection::parse_rows("5-2").is_err());
+
+    let by_batch = SampleSelection { batches: vec!["Plate 1".to_string()], ..Default::default() };
+    assert!(ids(&by_batch).is_empty()); // Illumina sample sheets have no batch column by default
+}
@@ -0,0 +1,129 @@
+use normalisation::apply_normalisation::NormalizationConfig;
+use normalisation::stage2::{Axis, SortedAxis, Translation};
+use normalisation::stage3::Rotation;
+use normalisation::stage4::Shear;
+use normalisation::stage5::Scale;
+
+mod common;
+use common::arm;
+
+#[test]
+fn sorted_axis_finds_the_same_points_as_a_linear_scan() {
+    // Repeated and negative values, and sweep positions exactly between two points, exercise the tie-breaking
+    let data: Vec<(f64, f64)> = (0..500)
+        .map(|i| {
+            let x = ((i * 37) % 101) as f64 * 2.0 - 60.0;
+            let y = ((i * 53) % 89) as f64 * 0.25;
+            (x, y)
+        })
+        .collect();
+
+    let (by_x, by_y) = (SortedAxis::new(&data, Axis::X), SortedAxis::new(&data, Axis::Y));
+    for step in 0..=800 {
+        let position = -70.0 + step as f64 * 0.3;
+        assert_eq!(by_x.nearest(position), Some(Translation::find_closest(position, 'x', &data)), "x = {}", position);
+        assert_eq!(by_y.nearest(position / 8.0), Some(Translation::find_closest(position / 8.0, 'y', &data)), "y = {}", position / 8.0);
+    }
+    for midpoint in (-61..=143).step_by(2) {
+        let position = midpoint as f64;
+        assert_eq!(by_x.nearest(position), Some(Translation::find_closest(position, 'x', &data)), "x = {}", position);
+    }
+
+    assert_eq!(SortedAxis::new(&[], Axis::X).nearest(1.0), None);
+}
+
+#[test]
+fn ties_go_to_the_point_earlier_in_the_group() {
+    let data = vec![(3.0, 0.0), (1.0, 0.0), (f64::NAN, 1.0), (1.0, 2.0), (3.0, 2.0)];
+    let by_x = SortedAxis::new(&data, Axis::X);
+
+    assert_eq!(Axis::X.nearest_in(&data, 2.0), Some((3.0, 0.0)));
+    assert_eq!(by_x.nearest(2.0), Some((3.0, 0.0)));
+    // Nearer by less than one is still nearer, and NaN is never the nearest
+    assert_eq!(Axis::X.nearest_in(&data, 1.4), Some((1.0, 0.0)));
+    assert_eq!(by_x.nearest(1.4), Some((1.0, 0.0)));
+    assert_eq!(Axis::X.nearest_in(&data, 10.0), Some((3.0, 0.0)));
+    assert_eq!(by_x.nearest(10.0), Some((3.0, 0.0)));
+}
+
+#[test]
+fn sweeps_at_small_intensities_follow_the_arm() {
+    // At 0.02 apart along x, from the origin to (1, tan 0.2), as after translation
+    let data = arm(51, |x| (x, 0.2_f64.tan() * x));
+    let by_x = SortedAxis::new(&data, Axis::X);
+    let step = (1.0 - 0.02) / 49.0;
+
+    for i in 0..50 {
+        let position = 0.02 + i as f64 * step;
+        let expected = data[49 - i];
+        assert_eq!(Axis::X.nearest_in(&data, position), Some(expected), "x = {}", position);
+        assert_eq!(by_x.nearest(position), Some(expected), "x = {}", position);
+    }
+}
+
+#[test]
+fn lines_are_fitted_to_the_points() {
+    let points: Vec<(f64, f64)> = (0..40).map(|i| (i as f64 * 37.5 - 200.0, 0.35 * (i as f64 * 37.5 - 200.0) + 812.0)).collect();
+    let tolerance = NormalizationConfig::default().fit_tolerance;
+
+    for (slope, intercept) in [Translation::fit_line(&points, tolerance), Translation::fit_line_p(&points, tolerance)] {
+        assert!((slope - 0.35).abs() < 1e-9, "slope {}", slope);
+        assert!((intercept - 812.0).abs() < 1e-6, "intercept {}", intercept);
+    }
+}
+
+#[test]
+fn rotation_finds_the_homozygote_a_arm_at_small_intensities() {
+    let theta: f64 = 0.2;
+    let data = arm(51, |x| (x, theta.tan() * x));
+    let config = NormalizationConfig::default();
+
+    assert!((Rotation::rotate(&mut data.clone(), 0.0, 0.0, &config) - theta).abs() < 1e-9);
+    assert!((Rotation::rotate_p(&mut data.clone(), 0.0, 0.0, &config) - theta).abs() < 1e-9);
+}
+
+#[test]
+fn shear_finds_the_homozygote_b_arm_at_small_intensities() {
+    // Steep, as the homozygote B arm is once the A arm lies on the x axis
+    let data = arm(51, |y| (0.1 * y, y));
+    let config = NormalizationConfig::default();
+    let angle = 10.0_f64.atan();
+
+    assert!((Shear::shear_unparallelised(&mut data.clone(), 0.0, &config) - angle).abs() < 1e-9);
+    assert!((Shear::shear_p(&mut data.clone(), 0.0, &config) - angle).abs() < 1e-9);
+}
+
+#[test]
+fn scale_sweeps_find_both_homozygote_arms_at_small_intensities() {
+    // Both arms lie on the axes, the shear already removed, and each sweep has to average its own arm
+    let mut data = arm(51, |x| (x, 0.0));
+    data.extend(arm(51, |y| (0.0, y / 2.0)));
+    let config = NormalizationConfig::default();
+
+    for scale in [Scale::scale_p(&mut data.clone(), 0.0, &config), Scale::scale(&mut data.clone(), 0.0, &config)] {
+        assert!((scale.0 - 0.5).abs() < 0.01, "scale_x {}", scale.0);
+        assert!((scale.1 - 0.25).abs() < 0.01, "scale_y {}", scale.1);
+    }
+}
+
+#[test]
+fn both_scale_paths_remove_the_same_shear() {
+    // The homozygote B arm leans over by the shear angle until the scale stage removes it
+    let shear: f64 = 0.3;
+    let mut data = arm(51, |x| (x, 0.0));
+    data.extend(arm(51, |y| (shear.tan() * y / 2.0, y / 2.0)));
+    let config = NormalizationConfig::default();
+
+    // The two paths sum the control points in a different order, so they agree up to rounding
+    let close = |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12;
+    let (mut parallel, mut unparallelised) = (data.clone(), data);
+    let scale = Scale::scale_p(&mut parallel, shear, &config);
+    let unparallelised_scale = Scale::scale(&mut unparallelised, shear, &config);
+    assert!(close(scale, unparallelised_scale), "{:?} and {:?}", scale, unparallelised_scale);
+    assert!((scale.0 - 0.5).abs() < 0.01, "scale_x {}", scale.0);
+    assert!((scale.1 - 0.25).abs() < 0.01, "scale_y {}", scale.1);
+
+    // The B arm ends up on the y axis
+    assert!(parallel[51..].iter().all(|&(x, _)| x.abs() < 1e-12), "{:?}", &parallel[51..]);
+    assert!(parallel.iter().zip(&unparallelised).all(|(&a, &b)| close(a, b)));
+}
@@ -0,0 +1,180 @@
+use normalisation::apply_normalisation::{NormalizationConfig, NormalizationTransform, Normalise, TransformRecord, TransformTable};
+use normalisation::stage1::Outliers;
+use normalisation::Error;
+use std::collections::HashMap;
+use std::f64::consts::FRAC_PI_4;
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::{arm, genotype_clusters};
+
+fn transform() -> NormalizationTransform {
+    NormalizationTransform { offset_x: 120.0, offset_y: -35.5, theta: 0.12, shear: -0.3, scale_x: 850.0, scale_y: 1210.0 }
+}
+
+#[test]
+fn inverting_recovers_the_raw_intensities() {
+    let transform = transform();
+    for point in [(0.0, 0.0), (5300.0, 420.0), (310.0, 7800.0), (-12.5, 3.0)] {
+        let (x, y) = transform.invert(transform.apply(point));
+        assert!((x - point.0).abs() < 1e-9 && (y - point.1).abs() < 1e-9, "{:?} came back as {:?}", point, (x, y));
+    }
+    assert_eq!(NormalizationTransform::IDENTITY.apply((5300.0, 420.0)), (5300.0, 420.0));
+}
+
+#[test]
+fn transforms_serialise_without_loss() {
+    let transform = transform();
+    assert_eq!(NormalizationTransform::from_json(&transform.to_json()).unwrap(), transform);
+}
+
+#[test]
+fn normalised_snps_are_the_fitted_transform_applied_to_the_raw_intensities() {
+    let raw = genotype_clusters();
+    let snp = Arc::new(Mutex::new(raw.clone()));
+    let transform = Normalise::within_snp_normalisation(&snp, &NormalizationConfig::default()).unwrap();
+
+    assert_eq!(transform, Normalise::fit_transform(&raw, &NormalizationConfig::default()).unwrap().0);
+    let expected: Vec<(f64, f64)> = raw.iter().map(|&point| transform.apply(point)).collect();
+    assert_eq!(*snp.lock().unwrap(), expected);
+}
+
+#[test]
+fn too_few_points_leave_the_snp_unchanged() {
+    let raw = vec![(4000.0, 300.0), (250.0, 3600.0), (2100.0, 1900.0), (3900.0, 350.0)];
+    let snp = Arc::new(Mutex::new(raw.clone()));
+    assert!(Normalise::within_snp_normalisation(&snp, &NormalizationConfig::default()).is_err());
+    assert_eq!(*snp.lock().unwrap(), raw);
+}
+
+#[test]
+fn transform_tables_read_back_bit_for_bit() {
+    let mut degenerate = transform();
+    degenerate.scale_y = f64::NAN;
+    let record = |sample: &str, bead_set_id, transform| TransformRecord { sample: sample.to_string(), bead_set_id, points: 412, outliers: 9, transform };
+    let records = vec![record("S1", 1, transform()), record("S1", 2, NormalizationTransform { theta: 0.1 + 0.2, ..transform() })];
+    let table = TransformTable { manifest_hash: u64::MAX - 1, records };
+
+    let mut tsv = Vec::new();
+    table.write_tsv_to(&mut tsv).unwrap();
+    let tsv = String::from_utf8(tsv).unwrap();
+    assert_eq!(TransformTable::from_tsv(&tsv).unwrap(), table);
+
+    // A table that does not say which manifest it was fitted with cannot be checked against the manifest of a run
+    let (_, without_hash) = tsv.split_once('\n').unwrap();
+    assert!(TransformTable::from_tsv(without_hash).is_err());
+
+    let path = std::env::temp_dir().join(format!("{}_transforms.json", std::process::id())).to_string_lossy().into_owned();
+    table.write_json(&path).unwrap();
+    assert_eq!(TransformTable::read_json(&path).unwrap(), table);
+    std::fs::remove_file(&path).unwrap();
+
+    let table = TransformTable { manifest_hash: 7, records: vec![record("S2", 1, degenerate)] };
+    let mut tsv = Vec::new();
+    table.write_tsv_to(&mut tsv).unwrap();
+    let read_back = TransformTable::from_tsv(std::str::from_utf8(&tsv).unwrap()).unwrap();
+    assert!(read_back.records[0].transform.scale_y.is_nan());
+}
+
+#[test]
+fn stored_transforms_reproduce_the_fitted_run_bit_for_bit() {
+    let raw: HashMap<i32, Vec<(f64, f64)>> = HashMap::from([(1, genotype_clusters()), (2, genotype_clusters().into_iter().rev().collect())]);
+    let vector_names = Arc::new(Mutex::new(vec![1, 2]));
+
+    let mut fitted = raw.clone();
+    let (records, _) = Normalise::within_beadset_normalisation("S1", &mut fitted, &vector_names, &NormalizationConfig::default()).unwrap();
+
+    // Through the TSV, as a re-analysis would read them
+    let mut tsv = Vec::new();
+    TransformTable { manifest_hash: 7, records }.write_tsv_to(&mut tsv).unwrap();
+    let stored = TransformTable::from_tsv(std::str::from_utf8(&tsv).unwrap()).unwrap().into_samples();
+
+    let mut applied = raw.clone();
+    Normalise::apply_beadset_transforms("S1", &mut applied, &vector_names, &stored["S1"]).unwrap();
+    assert_eq!(applied, fitted);
+
+    let mut other = raw;
+    assert!(matches!(
+        Normalise::apply_beadset_transforms("S2", &mut other, &vector_names, &stored["S1"][..1]),
+        Err(Error::MissingTransform { bead_set_id: 2, .. })
+    ));
+}
+
+#[test]
+fn small_beadset_groups_are_left_as_they_are() {
+    let tiny = vec![(100.0, 200.0), (300.0, 50.0)];
+    let raw: HashMap<i32, Vec<(f64, f64)>> = HashMap::from([(1, genotype_clusters()), (2, tiny.clone()), (3, Vec::new())]);
+    let vector_names = Arc::new(Mutex::new(vec![1, 2, 3]));
+
+    let mut fitted = raw.clone();
+    let (records, degenerate) = Normalise::within_beadset_normalisation("S1", &mut fitted, &vector_names, &NormalizationConfig::default()).unwrap();
+
+    // The empty group has no transform, the 2-point group keeps its intensities and the identity
+    assert_eq!(records.iter().map(|record| record.bead_set_id).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(records[1].transform, NormalizationTransform::IDENTITY);
+    assert_eq!(fitted[&2], tiny);
+    assert_ne!(fitted[&1], raw[&1]);
+    assert!(matches!(degenerate[..], [Error::DegenerateBeadset { bead_set_id: 2, points: 2 }]));
+
+    // The stored identity gives the same intensities again
+    let mut applied = raw;
+    Normalise::apply_beadset_transforms("S1", &mut applied, &vector_names, &records).unwrap();
+    assert_eq!(applied, fitted);
+}
+
+#[test]
+fn outlier_thresholds_follow_the_config() {
+    let removed = |config: &NormalizationConfig| Outliers::remove_outliers_parallelised(&mut genotype_clusters(), config).map(|outliers| outliers.len());
+    let default_outliers = removed(&NormalizationConfig::default()).unwrap();
+    let wider = NormalizationConfig { outlier_rank: 15, lower_percentile: 0.25, upper_percentile: 0.75, ..Default::default() };
+    let wider_outliers = removed(&wider).unwrap();
+    assert!(wider_outliers > default_outliers, "{} outliers with the wider thresholds, {} by default", wider_outliers, default_outliers);
+
+    assert!(matches!(
+        Outliers::remove_outliers_parallelised(&mut genotype_clusters()[..10].to_vec(), &wider),
+        Err(Error::TooFewPoints { points: 10, required: 15 })
+    ));
+    assert!(NormalizationConfig { lower_percentile: 0.5, upper_percentile: 0.5, ..Default::default() }.validate().is_err());
+}
+
+#[test]
+fn fitted_scales_are_half_the_length_of_each_homozygote_arm() {
+    // The A arm lies on the x axis from (500, 0) with a point under every x-sweep position. The B arm leaves the same point at 45
+    // degrees with its x between those positions, so the sweeps of each arm only find its own points. Three points beyond every
+    // threshold are the only outliers
+    let (intensity, step) = (2000.0, 0.005);
+    let mut data: Vec<(f64, f64)> = (0..400).map(|i| (0.25 + i as f64 * step, 0.0)).collect();
+    data.extend((0..200).map(|j| (j as f64 + 0.5) * step).map(|t| (0.25 + t, t)));
+    data.extend([(1.0, -0.5), (-0.5, 1.0), (10.0, 10.0)]);
+    let data: Vec<(f64, f64)> = data.into_iter().map(|(x, y)| (x * intensity, y * intensity)).collect();
+    let config = NormalizationConfig { outlier_rank: 1, lower_percentile: 0.0, upper_percentile: 1.0, ..NormalizationConfig::default() };
+
+    let (transform, outliers) = Normalise::fit_transform(&data, &config).unwrap();
+    assert_eq!(outliers, 3);
+    assert!((transform.offset_x - 500.0).abs() < 1e-6 && transform.offset_y.abs() < 1e-6, "{:?}", transform);
+    assert!(transform.theta.abs() < 1e-9 && (transform.shear - FRAC_PI_4).abs() < 1e-9, "{:?}", transform);
+    // Arms of 399 and 199.5 steps
+    assert!((transform.scale_x - 1995.0).abs() < 1.0, "scale_x {}", transform.scale_x);
+    assert!((transform.scale_y - 997.5).abs() < 5.0, "scale_y {}", transform.scale_y);
+}
+
+#[test]
+fn small_intensities_give_back_the_transform_they_were_drawn_with() {
+    // Homozygote arms at intensities below 1, as after translation, drawn in normalised coordinates and mapped back with a
+    // known transform. Each arm has a point under every sweep position, and the B arm starts just below the A arm so that the
+    // lowest point of the shear and scale sweeps is on it. At 45 degrees the angle of the B arm is also its shear. Three points
+    // beyond every threshold are the only outliers
+    let known = NormalizationTransform { offset_x: 0.05, offset_y: 0.03, theta: 0.15, shear: FRAC_PI_4, scale_x: 0.8, scale_y: 0.5 };
+    let config = NormalizationConfig { outlier_rank: 1, lower_percentile: 0.0, upper_percentile: 1.0, ..NormalizationConfig::default() };
+    let mut data = arm(config.sweep_points, |u| known.invert((u, 0.0)));
+    data.extend(arm(config.sweep_points, |v| known.invert((0.0, v - 1e-9))));
+    data.extend([(1.0, -0.5), (-0.5, 1.0), (10.0, 10.0)]);
+
+    let (transform, outliers) = Normalise::fit_transform(&data, &config).unwrap();
+    assert_eq!(outliers, 3);
+    let close = |fitted: f64, expected: f64| (fitted - expected).abs() < 1e-8;
+    assert!(close(transform.offset_x, 0.05) && close(transform.offset_y, 0.03), "{:?}", transform);
+    assert!(close(transform.theta, 0.15) && close(transform.shear, FRAC_PI_4), "{:?}", transform);
+    // Half the length of each arm
+    assert!(close(transform.scale_x, 0.4) && close(transform.scale_y, 0.25), "{:?}", transform);
+}